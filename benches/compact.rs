@@ -0,0 +1,61 @@
+//! Lookups in a churned in use map before and after
+//! [`ResourceCache::compact`].
+//!
+//! Run with `cargo bench --bench compact`.
+
+use std::{
+    hint::black_box,
+    num::NonZeroUsize,
+    time::{Duration, Instant},
+};
+
+use asset_cache::ResourceCache;
+
+const LIVE: usize = 1_000;
+const CHURNED: usize = 200_000;
+const LOOKUPS: usize = 10_000_000;
+
+fn time_lookups(res: &mut ResourceCache, keys: &[String]) -> Duration {
+    let start = Instant::now();
+    for i in 0..LOOKUPS {
+        black_box(res.get_raw(black_box(&keys[i % keys.len()])));
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let mut res = ResourceCache::new(NonZeroUsize::new(1).unwrap());
+    let live: Vec<String> = (0..LIVE).map(|i| format!("live{}", i)).collect();
+    let _handles: Vec<_> = live.iter().map(|key| res.insert(key.clone(), 0)).collect();
+    // grow the map, then take the extra entries out again
+    let churned: Vec<_> = (0..CHURNED)
+        .map(|i| res.insert(format!("churn{}", i), 0))
+        .collect();
+    for handle in churned {
+        res.unload(handle.key());
+    }
+    let missing: Vec<String> = (0..LIVE).map(|i| format!("missing{}", i)).collect();
+
+    // warm up, so the first measurement isn't penalized
+    time_lookups(&mut res, &live);
+    let hits_before = time_lookups(&mut res, &live);
+    let misses_before = time_lookups(&mut res, &missing);
+    res.compact();
+    let hits_after = time_lookups(&mut res, &live);
+    let misses_after = time_lookups(&mut res, &missing);
+
+    println!(
+        "{} hits: {:?} before compact, {:?} after ({:.2}x)",
+        LOOKUPS,
+        hits_before,
+        hits_after,
+        hits_before.as_secs_f64() / hits_after.as_secs_f64()
+    );
+    println!(
+        "{} misses: {:?} before compact, {:?} after ({:.2}x)",
+        LOOKUPS,
+        misses_before,
+        misses_after,
+        misses_before.as_secs_f64() / misses_after.as_secs_f64()
+    );
+}
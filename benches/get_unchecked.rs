@@ -0,0 +1,45 @@
+//! Hot loop lookups with [`ResourceCache::get`] and
+//! [`ResourceCache::get_unchecked`].
+//!
+//! Run with `cargo bench --bench get_unchecked --features unstable`.
+
+use std::{hint::black_box, num::NonZeroUsize, time::Instant};
+
+use asset_cache::ResourceCache;
+
+const KEYS: usize = 8;
+const LOOKUPS: usize = 10_000_000;
+
+fn main() {
+    let mut res = ResourceCache::new(NonZeroUsize::new(KEYS).unwrap());
+    let keys: Vec<String> = (0..KEYS).map(|i| format!("asset{}", i)).collect();
+    let _assets: Vec<_> = keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| res.insert(key.clone(), i))
+        .collect();
+
+    // warm up, so the first measurement isn't penalized
+    for i in 0..LOOKUPS {
+        black_box(*res.get::<usize>(black_box(&keys[i % KEYS])).unwrap());
+    }
+    let start = Instant::now();
+    for i in 0..LOOKUPS {
+        black_box(*res.get::<usize>(black_box(&keys[i % KEYS])).unwrap());
+    }
+    let checked = start.elapsed();
+    let start = Instant::now();
+    for i in 0..LOOKUPS {
+        // SAFETY: every key was inserted as a usize and is still in use
+        black_box(*unsafe { res.get_unchecked::<usize>(black_box(&keys[i % KEYS])) });
+    }
+    let unchecked = start.elapsed();
+
+    println!(
+        "{} lookups: get {:?}, get_unchecked {:?} ({:.2}x)",
+        LOOKUPS,
+        checked,
+        unchecked,
+        checked.as_secs_f64() / unchecked.as_secs_f64()
+    );
+}
@@ -0,0 +1,81 @@
+use std::{error::Error, fmt};
+
+/// Errors returned by the fallible cache operations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CacheError {
+    /// The asset stored under `key` is of type `found`, not the requested
+    /// type `expected`.
+    WrongType {
+        key: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// No asset is stored under `key`.
+    NotFound { key: String },
+    /// A key is longer than the limit set with
+    /// [`ResourceCache::set_max_key_len`](crate::ResourceCache::set_max_key_len).
+    KeyTooLong { len: usize, max: usize },
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::WrongType {
+                key,
+                expected,
+                found,
+            } => write!(
+                f,
+                "asset {} is not of type `{}`, it was inserted as `{}`",
+                DisplayKey(key),
+                expected,
+                found
+            ),
+            CacheError::NotFound { key } => write!(f, "asset {} not found", DisplayKey(key)),
+            CacheError::KeyTooLong { len, max } => {
+                write!(
+                    f,
+                    "key of {} bytes is longer than the maximum of {}",
+                    len, max
+                )
+            }
+        }
+    }
+}
+
+impl Error for CacheError {}
+
+/// Formats a key quoted and escaped, cut short if it is long, so keys from
+/// untrusted sources can be shown safely.
+pub(crate) struct DisplayKey<'a>(pub(crate) &'a str);
+
+impl DisplayKey<'_> {
+    const MAX_LEN: usize = 64;
+}
+
+impl fmt::Display for DisplayKey<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let key = crate::truncate(self.0, Self::MAX_LEN);
+        if key.len() < self.0.len() {
+            write!(f, "{:?}...", key)
+        } else {
+            write!(f, "{:?}", key)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_key() {
+        assert_eq!(DisplayKey("a\0b").to_string(), "\"a\\0b\"");
+        let long = "x".repeat(1000);
+        assert_eq!(
+            DisplayKey(&long).to_string(),
+            format!("{:?}...", &long[..DisplayKey::MAX_LEN])
+        );
+    }
+}
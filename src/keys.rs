@@ -0,0 +1,107 @@
+use std::{fmt, marker::PhantomData};
+
+use crate::{Handle, ResourceCache};
+
+/// A string key tied to the type of asset stored under it, usually declared
+/// with [`asset_keys!`](crate::asset_keys).
+///
+/// Typed keys are only a compile time check layered over the string keys:
+/// `cache.get_key(TITLE_FONT)` is the same as `cache.get::<Font>(TITLE_FONT.key())`,
+/// and assets inserted with a plain string can be looked up with a typed key
+/// and the other way around.
+pub struct AssetKey<T: ?Sized> {
+    key: &'static str,
+    ty: PhantomData<fn() -> T>,
+}
+
+impl<T: ?Sized> AssetKey<T> {
+    pub const fn new(key: &'static str) -> Self {
+        Self {
+            key,
+            ty: PhantomData,
+        }
+    }
+
+    pub const fn key(&self) -> &'static str {
+        self.key
+    }
+}
+
+impl<T: ?Sized> Clone for AssetKey<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: ?Sized> Copy for AssetKey<T> {}
+
+impl<T: ?Sized> fmt::Debug for AssetKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AssetKey").field(&self.key).finish()
+    }
+}
+
+/// Declares [`AssetKey`] constants.
+///
+/// ```
+/// # struct Font;
+/// asset_cache::asset_keys! {
+///     pub TITLE_FONT: Font = "fonts/title.ttf",
+///     BODY_FONT: Font = "fonts/body.ttf",
+/// }
+/// assert_eq!(TITLE_FONT.key(), "fonts/title.ttf");
+/// ```
+#[macro_export]
+macro_rules! asset_keys {
+    ($($(#[$meta:meta])* $vis:vis $name:ident: $ty:ty = $key:expr),* $(,)?) => {
+        $(
+            $(#[$meta])*
+            $vis const $name: $crate::AssetKey<$ty> = $crate::AssetKey::new($key);
+        )*
+    };
+}
+
+impl ResourceCache {
+    pub fn insert_key<T: Send + Sync + 'static>(
+        &mut self,
+        key: AssetKey<T>,
+        value: T,
+    ) -> Handle<T> {
+        self.insert(key.key().to_owned(), value)
+    }
+
+    pub fn get_key<T: Send + Sync + 'static>(&mut self, key: AssetKey<T>) -> Option<Handle<T>> {
+        self.get(key.key())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+
+    struct Font(&'static str);
+
+    crate::asset_keys! {
+        TITLE_FONT: Font = "fonts/title.ttf",
+        /// documented
+        pub(crate) SIZE: i32 = "size",
+    }
+
+    #[test]
+    fn typed_keys() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let _ = res.insert_key(TITLE_FONT, Font("title"));
+        assert_eq!(res.get_key(TITLE_FONT).unwrap().0, "title");
+        assert!(res.get_key(SIZE).is_none());
+    }
+
+    #[test]
+    fn typed_and_string_keys() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let _ = res.insert(String::from("size"), 1);
+        assert_eq!(*res.get_key(SIZE).unwrap(), 1);
+        assert_eq!(*res.get::<i32>(SIZE.key()).unwrap(), 1);
+    }
+}
@@ -1,13 +1,138 @@
 #![allow(dead_code)]
 use std::{
-    any::Any, collections::HashMap, marker::PhantomData, num::NonZeroUsize, ops::Deref, sync::Arc,
+    any::{Any, TypeId},
+    borrow::Cow,
+    collections::{
+        hash_map::{DefaultHasher, HashMap, RandomState},
+        HashSet,
+    },
+    fmt,
+    hash::{BuildHasher, Hasher},
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    num::NonZeroUsize,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    ptr,
+    sync::{
+        atomic::{fence, AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use lru::LruCache;
 
+mod error;
+mod keys;
+mod multi;
+mod pending;
+mod raw_entry;
+mod read_through;
+mod shared;
+mod stats;
+mod unified;
+
+pub use error::CacheError;
+pub use keys::AssetKey;
+pub use multi::MultiResourceCache;
+pub use pending::{Pending, PendingHandle};
+pub use raw_entry::{OccupiedRawEntry, RawEntry, VacantRawEntry};
+pub use read_through::ReadThroughCache;
+pub use shared::{SharedGuard, SharedResourceCache};
+pub use stats::{CacheStats, StatsInterval};
+pub use unified::UnifiedCache;
+
+/// Capacity of the loaded tier for caches created with [`Default`].
+pub const DEFAULT_CAPACITY: NonZeroUsize = match NonZeroUsize::new(64) {
+    Some(capacity) => capacity,
+    None => unreachable!(),
+};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
 pub struct ResourceCache {
-    in_use: HashMap<String, RawHandle>,
-    loaded: LruCache<String, RawHandle>,
+    in_use: HashMap<String, RawHandle, KeyHasher>,
+    loaded: LruCache<String, RawHandle, KeyHasher>,
+    // id -> key, for every entry in either tier while `id_lookup` is set,
+    // sharing the key's allocation with the entry's handle
+    ids: HashMap<u64, Arc<str>>,
+    id_lookup: bool,
+    // (low, high), see `set_watermarks`
+    watermarks: Option<(usize, usize)>,
+    drop_hooks: HashMap<TypeId, DropHook>,
+    // key -> priority, see `pin_with_priority`
+    pins: HashMap<String, u8>,
+    // (stored type, target type) -> Coerce<target type>
+    coercions: HashMap<(TypeId, TypeId), Box<dyn Any + Send + Sync>>,
+    evict_callback: Option<EvictCallback>,
+    idle_callback: Option<Box<dyn FnMut() + Send>>,
+    // incremented on every access, orders in use entries by recency
+    clock: u64,
+    max_key_len: Option<(usize, KeyLengthPolicy)>,
+    min_lifetime: Duration,
+    stats: CacheStats,
+    stats_reporter: Option<stats::StatsReporter>,
+    // capacity of `in_use` at the last operation, to count rehashes
+    #[cfg(feature = "alloc-telemetry")]
+    in_use_capacity: usize,
+    // handles held elsewhere that `remove` doesn't count as users
+    extra_refs: usize,
+}
+
+/// What [`ResourceCache::get_or_refresh_with`] does when refreshing an
+/// expired entry fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefreshFailure {
+    /// Keep the expired entry and return it.
+    ServeStale,
+    /// Keep the expired entry but return the loader's error.
+    Propagate,
+}
+
+/// What to do with keys over the limit set with
+/// [`ResourceCache::set_max_key_len`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyLengthPolicy {
+    /// Inserting fails and lookups find nothing.
+    Reject,
+    /// Keys are cut to the limit (at a character boundary) on insert and on
+    /// lookup, so keys sharing a long prefix refer to the same entry.
+    Truncate,
+}
+
+// `Seeded` is only deterministic for a given toolchain: `DefaultHasher`'s
+// algorithm is unspecified and may change between Rust releases.
+#[derive(Clone)]
+enum KeyHasher {
+    Random(RandomState),
+    Seeded(u64),
+}
+
+impl BuildHasher for KeyHasher {
+    type Hasher = DefaultHasher;
+
+    fn build_hasher(&self) -> DefaultHasher {
+        match self {
+            KeyHasher::Random(state) => state.build_hasher(),
+            KeyHasher::Seeded(seed) => {
+                let mut hasher = DefaultHasher::new();
+                hasher.write_u64(*seed);
+                hasher
+            }
+        }
+    }
+}
+
+type EvictCallback = Box<dyn FnMut(&str, &RawHandle) -> EvictDecision + Send>;
+
+/// Returned by the callback set with [`ResourceCache::set_evict_callback`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvictDecision {
+    Evict,
+    /// Move the entry back to the most recently used position and pick
+    /// another victim.
+    Keep,
 }
 
 #[derive(Clone, Debug)]
@@ -17,14 +142,67 @@ impl RawHandle {
     fn ptr_eq(&self, other: &Self) -> bool {
         Arc::ptr_eq(&self.0, &other.0)
     }
+
+    /// Stable id assigned to this asset when it was inserted.
+    pub fn id(&self) -> u64 {
+        self.0.id
+    }
+
+    /// Key the asset was inserted under.
+    pub fn key(&self) -> &str {
+        &self.0.key
+    }
+
+    /// Key the asset was inserted under, sharing the handle's allocation.
+    pub fn key_arc(&self) -> Arc<str> {
+        self.0.key.clone()
+    }
+
+    /// Name of the type the asset was inserted as, for diagnostics.
+    pub fn type_name(&self) -> &'static str {
+        self.0.type_name
+    }
+
+    /// Number of weak references to the asset, for diagnostics.
+    pub fn weak_count(&self) -> usize {
+        Arc::weak_count(&self.0)
+    }
 }
 
 #[derive(Debug)]
 struct HandleInner<T: ?Sized> {
-    key: String,
+    key: Arc<str>,
+    id: u64,
+    drop_hook: Option<DropHook>,
+    // `ResourceCache::clock` when last inserted or looked up
+    last_access: AtomicU64,
+    created: Instant,
+    type_name: &'static str,
+    // set by `Handle::into_pin`, the value must never move or be mutated
+    address_pinned: AtomicBool,
+    // `BorrowHandle`s alive, which `ResourceCache::remove` doesn't count
+    borrows: AtomicUsize,
     value: T,
 }
 
+impl<T: ?Sized> Drop for HandleInner<T> {
+    fn drop(&mut self) {
+        if let Some(hook) = self.drop_hook.take() {
+            (hook.0)(&self.value as *const T as *const ());
+        }
+    }
+}
+
+// Called with a pointer to the value of the `HandleInner` it is attached to.
+#[derive(Clone)]
+struct DropHook(Arc<dyn Fn(*const ()) + Send + Sync>);
+
+impl fmt::Debug for DropHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DropHook")
+    }
+}
+
 // Invariant: type in RawHandle is T
 #[derive(Debug)]
 pub struct Handle<T: ?Sized> {
@@ -36,23 +214,89 @@ impl<T: ?Sized> Clone for Handle<T> {
     fn clone(&self) -> Self {
         Self {
             raw: self.raw.clone(),
-            ty: self.ty.clone(),
+            ty: self.ty,
         }
     }
 }
 
 impl<T: Send + Sync + 'static> Handle<T> {
-    fn new(key: String, value: T) -> Self {
+    fn new(key: &str, drop_hook: Option<DropHook>, value: T) -> Self {
+        // unique across caches, so handles can move between them
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
         Self {
-            raw: RawHandle(Arc::new(HandleInner { key, value })),
+            raw: RawHandle(Arc::new(HandleInner {
+                key: key.into(),
+                id,
+                drop_hook,
+                last_access: AtomicU64::new(0),
+                created: Instant::now(),
+                type_name: std::any::type_name::<T>(),
+                address_pinned: AtomicBool::new(false),
+                borrows: AtomicUsize::new(0),
+                value,
+            })),
             ty: PhantomData,
         }
     }
 }
 
-impl<T: Send + Sync + 'static> Into<RawHandle> for Handle<T> {
-    fn into(self) -> RawHandle {
-        self.raw
+impl<T: ?Sized> Handle<T> {
+    /// Stable id assigned to this asset when it was inserted.
+    ///
+    /// Unlike the handle itself the id is `Copy` and can be resolved back
+    /// with [`ResourceCache::get_by_id`] while the asset is cached, if id
+    /// lookup is enabled.
+    pub fn id(&self) -> u64 {
+        self.raw.id()
+    }
+
+    /// Key the asset was inserted under.
+    pub fn key(&self) -> &str {
+        self.raw.key()
+    }
+
+    /// Key the asset was inserted under, sharing the handle's allocation so
+    /// it can be stored elsewhere without copying the string.
+    pub fn key_arc(&self) -> Arc<str> {
+        self.raw.key_arc()
+    }
+
+    /// Number of weak references to the asset, for diagnostics.
+    pub fn weak_count(&self) -> usize {
+        self.raw.weak_count()
+    }
+}
+
+impl<T: Send + Sync + 'static> From<Handle<T>> for RawHandle {
+    fn from(handle: Handle<T>) -> Self {
+        handle.raw
+    }
+}
+
+impl<T: Send + Sync + 'static> Handle<T> {
+    /// Returns the asset, same as dereferencing the handle.
+    pub fn value(&self) -> &T {
+        // use unsafe here?
+        self.raw.0.value.downcast_ref().unwrap()
+    }
+}
+
+impl<T: Send + Sync + 'static> Handle<T> {
+    /// Pins the asset, e.g. to pass it to APIs taking `Pin<&T>` through
+    /// [`Pin::as_ref`].
+    ///
+    /// The asset lives behind an `Arc` so its address never changes while
+    /// any handle to it exists. The only ways the cache could move or mutate
+    /// it, [`ResourceCache::get_cow`] and [`ResourceCache::iter_mut`], skip
+    /// assets that have been pinned, so the asset stays in place until it is
+    /// dropped, as `Pin` requires.
+    pub fn into_pin(self) -> Pin<Handle<T>> {
+        // Release, paired with the acquire after the cache sees it holds the
+        // only reference, which happens once this handle is dropped
+        self.raw.0.address_pinned.store(true, Ordering::Release);
+        // SAFETY: see above, the value is never moved out and no `&mut T` is
+        // handed out once `address_pinned` is set.
+        unsafe { Pin::new_unchecked(self) }
     }
 }
 
@@ -60,11 +304,100 @@ impl<T: Send + Sync + 'static> Deref for Handle<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        // use unsafe here?
-        &self.raw.0.value.downcast_ref().unwrap()
+        self.value()
+    }
+}
+
+impl<T: Send + Sync + 'static> Handle<T> {
+    /// Returns a handle to the same asset that doesn't count as a user when
+    /// this handle is given back with [`ResourceCache::remove`].
+    ///
+    /// Borrows keep the asset alive like any handle, but `remove` demotes
+    /// the entry once the handle passed to it is the last one that isn't a
+    /// borrow, e.g. when views hand out borrows of an asset owned elsewhere.
+    pub fn borrow(&self) -> BorrowHandle<T> {
+        self.raw.0.borrows.fetch_add(1, Ordering::Relaxed);
+        BorrowHandle {
+            handle: self.clone(),
+        }
+    }
+}
+
+/// A handle that doesn't keep an entry in use, see [`Handle::borrow`].
+pub struct BorrowHandle<T> {
+    handle: Handle<T>,
+}
+
+impl<T: Send + Sync + 'static> BorrowHandle<T> {
+    pub fn id(&self) -> u64 {
+        self.handle.id()
+    }
+
+    pub fn key(&self) -> &str {
+        self.handle.key()
+    }
+}
+
+impl<T: Send + Sync + 'static> Clone for BorrowHandle<T> {
+    fn clone(&self) -> Self {
+        self.handle.borrow()
+    }
+}
+
+impl<T> Drop for BorrowHandle<T> {
+    fn drop(&mut self) {
+        self.handle.raw.0.borrows.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<T: Send + Sync + 'static> Deref for BorrowHandle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+type Coerce<U> = Arc<dyn Fn(&(dyn Any + Send + Sync)) -> &U + Send + Sync>;
+
+/// A handle to an asset viewed as `U`, usually a trait object, returned by
+/// [`ResourceCache::get_as`].
+pub struct DynHandle<U: ?Sized> {
+    raw: RawHandle,
+    coerce: Coerce<U>,
+}
+
+impl<U: ?Sized> Clone for DynHandle<U> {
+    fn clone(&self) -> Self {
+        Self {
+            raw: self.raw.clone(),
+            coerce: self.coerce.clone(),
+        }
+    }
+}
+
+impl<U: ?Sized> From<DynHandle<U>> for RawHandle {
+    fn from(handle: DynHandle<U>) -> Self {
+        handle.raw
+    }
+}
+
+impl<U: ?Sized> Deref for DynHandle<U> {
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        (self.coerce)(&self.raw.0.value)
     }
 }
 
+// Pins down the higher-ranked signature for closure inference.
+fn coerce_fn<U: ?Sized, F>(f: F) -> F
+where
+    F: Fn(&(dyn Any + Send + Sync)) -> &U,
+{
+    f
+}
+
 impl RawHandle {
     pub fn downcast<T: Send + Sync + 'static>(self) -> Result<Handle<T>, RawHandle> {
         if self.0.value.is::<T>() {
@@ -76,136 +409,1436 @@ impl RawHandle {
             Err(self)
         }
     }
+
+    // Whether `into_value` can move the value out.
+    fn is_unique(&self) -> bool {
+        if Arc::strong_count(&self.0) != 1 {
+            return false;
+        }
+        // synchronizes with the release decrement of the last other handle
+        // dropped, possibly on another thread, so its `into_pin` is seen
+        fence(Ordering::Acquire);
+        !self.0.address_pinned.load(Ordering::Acquire)
+    }
+
+    // Moves the value out if this is the only reference to it.
+    fn into_value<T: Send + Sync + 'static>(self) -> Result<T, RawHandle> {
+        if !self.0.value.is::<T>() || !self.is_unique() {
+            return Err(self);
+        }
+        let ptr = Arc::into_raw(self.0) as *const HandleInner<T>;
+        // SAFETY: the type was checked above and the Arc was created as an
+        // `Arc<HandleInner<T>>` before being coerced to `dyn Any`.
+        let inner = unsafe { Arc::from_raw(ptr) };
+        let mut inner = match Arc::try_unwrap(inner) {
+            Ok(inner) => inner,
+            Err(_) => unreachable!("strong count checked above"),
+        };
+        // the value is moved out, not dropped
+        inner.drop_hook = None;
+        let inner = ManuallyDrop::new(inner);
+        // SAFETY: `inner` is never used again, so each field is read once.
+        let (key, value) = unsafe { (ptr::read(&inner.key), ptr::read(&inner.value)) };
+        drop(key);
+        Ok(value)
+    }
 }
 
 impl ResourceCache {
     pub fn new(capacity: NonZeroUsize) -> Self {
+        Self::with_hasher(capacity, KeyHasher::Random(RandomState::new()))
+    }
+
+    /// Creates a cache whose key hashing, and so the iteration order of the
+    /// in use tier, only depends on `seed` for a given Rust toolchain.
+    ///
+    /// Meant for reproducible tests. Keys are hashed with the standard
+    /// library's [`DefaultHasher`], whose algorithm is unspecified and may
+    /// change between Rust releases, so the order is stable across runs but
+    /// not necessarily across compiler upgrades; snapshots of it may need
+    /// updating then. The hashes are predictable, so this should not be used
+    /// with keys from untrusted sources.
+    pub fn with_seeded_hasher(capacity: NonZeroUsize, seed: u64) -> Self {
+        Self::with_hasher(capacity, KeyHasher::Seeded(seed))
+    }
+
+    fn with_hasher(capacity: NonZeroUsize, hasher: KeyHasher) -> Self {
         Self {
-            in_use: HashMap::new(),
-            loaded: LruCache::new(capacity),
+            in_use: HashMap::with_hasher(hasher.clone()),
+            loaded: LruCache::with_hasher(capacity, hasher),
+            ids: HashMap::new(),
+            id_lookup: false,
+            watermarks: None,
+            drop_hooks: HashMap::new(),
+            pins: HashMap::new(),
+            coercions: HashMap::new(),
+            evict_callback: None,
+            idle_callback: None,
+            clock: 0,
+            max_key_len: None,
+            min_lifetime: Duration::ZERO,
+            stats: CacheStats::default(),
+            stats_reporter: None,
+            #[cfg(feature = "alloc-telemetry")]
+            in_use_capacity: 0,
+            extra_refs: 0,
         }
     }
 
+    /// # Panics
+    ///
+    /// Panics if `key` is longer than allowed with
+    /// [`KeyLengthPolicy::Reject`]; use [`try_insert`](Self::try_insert) for
+    /// keys from untrusted sources.
     pub fn insert<T: Send + Sync + 'static>(&mut self, key: String, value: T) -> Handle<T> {
-        let _ = self.loaded.pop(&key);
-        let handle = Handle::new(key.clone(), value);
-        self.in_use.insert(key, handle.clone().into());
-        handle
+        match self.try_insert(key, value) {
+            Ok(handle) => handle,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Like [`insert`](Self::insert), but returns [`CacheError::KeyTooLong`]
+    /// instead of panicking if the key is rejected.
+    pub fn try_insert<T: Send + Sync + 'static>(
+        &mut self,
+        mut key: String,
+        value: T,
+    ) -> Result<Handle<T>, CacheError> {
+        let len = self.check_key(&key)?.len();
+        key.truncate(len);
+        if let Some(old) = self.loaded.pop(&key) {
+            self.ids.remove(&old.id());
+        }
+        let drop_hook = self.drop_hooks.get(&TypeId::of::<T>()).cloned();
+        let handle = Handle::new(&key, drop_hook, value);
+        self.mark_used(&handle.raw);
+        self.track_id(&handle.raw);
+        if let Some(old) = self.in_use.insert(key, handle.clone().into()) {
+            self.ids.remove(&old.id());
+        }
+        self.stats.inserts += 1;
+        self.tick_stats();
+        Ok(handle)
+    }
+
+    /// Limits the length of keys in bytes, for caches fed keys from
+    /// untrusted sources. `None` removes the limit.
+    ///
+    /// Only affects keys passed in afterwards; entries already stored under
+    /// longer keys stay, reachable only through their handles.
+    pub fn set_max_key_len(&mut self, max: Option<usize>, policy: KeyLengthPolicy) {
+        self.max_key_len = max.map(|max| (max, policy));
+    }
+
+    // Applies the key length limit.
+    fn check_key<'k>(&self, key: &'k str) -> Result<&'k str, CacheError> {
+        match self.max_key_len {
+            Some((max, policy)) if key.len() > max => match policy {
+                KeyLengthPolicy::Reject => Err(CacheError::KeyTooLong {
+                    len: key.len(),
+                    max,
+                }),
+                KeyLengthPolicy::Truncate => Ok(truncate(key, max)),
+            },
+            _ => Ok(key),
+        }
+    }
+
+    /// Like [`insert`](Self::insert), but if an equal value of the same type
+    /// is already stored under `key` its handle is returned instead, so
+    /// existing handles stay valid. The returned `bool` is `true` if `value`
+    /// was inserted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is rejected, see
+    /// [`try_insert_if_changed`](Self::try_insert_if_changed).
+    pub fn insert_if_changed<T: PartialEq + Send + Sync + 'static>(
+        &mut self,
+        key: String,
+        value: T,
+    ) -> (Handle<T>, bool) {
+        match self.try_insert_if_changed(key, value) {
+            Ok(inserted) => inserted,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Like [`insert_if_changed`](Self::insert_if_changed), but returns
+    /// [`CacheError::KeyTooLong`] instead of panicking if the key is
+    /// rejected.
+    pub fn try_insert_if_changed<T: PartialEq + Send + Sync + 'static>(
+        &mut self,
+        key: String,
+        value: T,
+    ) -> Result<(Handle<T>, bool), CacheError> {
+        self.check_key(&key)?;
+        match self.get::<T>(&key) {
+            Some(handle) if *handle == value => Ok((handle, false)),
+            _ => Ok((self.try_insert(key, value)?, true)),
+        }
     }
 
+    /// Looks up the asset under `key`, returning `None` if it is missing or
+    /// not of type `T`.
+    ///
+    /// `T` must be exactly the type it was inserted as. An asset inserted as
+    /// `Box<dyn Trait + Send + Sync>` is only found under that type, not as
+    /// the concrete type inside the box, and `Box::new(value)` inserted
+    /// without a type annotation is stored as `Box<Concrete>`. Use
+    /// [`try_get`](Self::try_get) to see which type was actually stored.
     pub fn get<T: Send + Sync + 'static>(&mut self, key: &str) -> Option<Handle<T>> {
         self.get_raw(key).and_then(|x| x.downcast().ok())
     }
 
+    /// Registers `f` to be called with every asset of type `T` right before it
+    /// is dropped.
+    ///
+    /// The value is dropped once it has left the cache (evicted, overwritten
+    /// or the cache itself dropped) *and* the last handle to it is gone, so
+    /// the hook may run long after eviction, on whichever thread releases the
+    /// last handle. Values moved out by [`get_cow`](Self::get_cow) are not
+    /// dropped and don't trigger the hook.
+    ///
+    /// The hook is attached when an asset is inserted; assets inserted before
+    /// registering are not affected. Registering again for the same type
+    /// replaces the previous hook.
+    pub fn register_drop_hook<T: Send + Sync + 'static>(
+        &mut self,
+        f: impl Fn(&T) + Send + Sync + 'static,
+    ) {
+        let hook = move |value: *const ()| {
+            // SAFETY: the hook is only attached to `HandleInner<T>`, which
+            // passes a pointer to its value.
+            f(unsafe { &*(value as *const T) })
+        };
+        self.drop_hooks
+            .insert(TypeId::of::<T>(), DropHook(Arc::new(hook)));
+    }
+
+    /// Registers how to view assets of type `C` as `U`, so they can be
+    /// retrieved with [`get_as`](Self::get_as), e.g.
+    /// `register_coercion::<Button, dyn Widget>(|c| c)`.
+    pub fn register_coercion<C: Send + Sync + 'static, U: ?Sized + 'static>(
+        &mut self,
+        f: fn(&C) -> &U,
+    ) {
+        let coerce: Coerce<U> = Arc::new(coerce_fn(move |value| {
+            f(value
+                .downcast_ref()
+                .expect("coercion registered for this type"))
+        }));
+        self.coercions
+            .insert((TypeId::of::<C>(), TypeId::of::<U>()), Box::new(coerce));
+    }
+
+    /// Returns the asset stored under `key` viewed as `U`, using a coercion
+    /// registered with [`register_coercion`](Self::register_coercion) for the
+    /// asset's type. Returns `None` if there is no entry or no coercion.
+    pub fn get_as<U: ?Sized + 'static>(&mut self, key: &str) -> Option<DynHandle<U>> {
+        let raw = self.get_raw(key)?;
+        let stored = Any::type_id(&raw.0.value);
+        let coerce = self
+            .coercions
+            .get(&(stored, TypeId::of::<U>()))?
+            .downcast_ref::<Coerce<U>>()?
+            .clone();
+        Some(DynHandle { raw, coerce })
+    }
+
+    /// Returns the asset stored under `key` if it was inserted less than `ttl`
+    /// ago, otherwise inserts the value produced by `loader` in its place.
+    ///
+    /// If `loader` fails while replacing an expired entry, `on_failure`
+    /// decides whether the stale asset or the error is returned; the stale
+    /// entry stays cached either way. If there is no entry the error is
+    /// always returned.
+    ///
+    /// A rejected key is returned as [`CacheError::KeyTooLong`] without
+    /// calling `loader`.
+    pub fn get_or_refresh_with<T: Send + Sync + 'static, E: From<CacheError>>(
+        &mut self,
+        key: &str,
+        ttl: Duration,
+        on_failure: RefreshFailure,
+        loader: impl FnOnce() -> Result<T, E>,
+    ) -> Result<Handle<T>, E> {
+        let key = self.check_key(key)?;
+        match self.get::<T>(key) {
+            Some(handle) if handle.raw.0.created.elapsed() < ttl => Ok(handle),
+            Some(stale) => match loader() {
+                Ok(value) => Ok(self.try_insert(key.to_owned(), value)?),
+                Err(_) if on_failure == RefreshFailure::ServeStale => Ok(stale),
+                Err(err) => Err(err),
+            },
+            None => Ok(self.try_insert(key.to_owned(), loader()?)?),
+        }
+    }
+
+    /// Returns the asset stored under `key`, or inserts the value produced
+    /// by `f` if there is none.
+    ///
+    /// An existing entry of a different type is overwritten. `f` runs before
+    /// the cache is modified, so if it panics nothing is inserted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is rejected, without calling `f`; use
+    /// [`try_get_or_insert_with`](Self::try_get_or_insert_with) for keys
+    /// from untrusted sources.
+    pub fn get_or_insert_with<T: Send + Sync + 'static>(
+        &mut self,
+        key: &str,
+        f: impl FnOnce() -> T,
+    ) -> Handle<T> {
+        match self.try_get_or_insert_with(key, f) {
+            Ok(handle) => handle,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but returns
+    /// [`CacheError::KeyTooLong`] instead of panicking if the key is
+    /// rejected.
+    pub fn try_get_or_insert_with<T: Send + Sync + 'static>(
+        &mut self,
+        key: &str,
+        f: impl FnOnce() -> T,
+    ) -> Result<Handle<T>, CacheError> {
+        let key = self.check_key(key)?;
+        match self.get(key) {
+            Some(handle) => Ok(handle),
+            None => self.try_insert(key.to_owned(), f()),
+        }
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), passing `ctx`
+    /// (e.g. a render device) to the loader.
+    ///
+    /// The context is passed per call rather than stored in the cache, so the
+    /// cache doesn't need a type parameter for it and the context doesn't
+    /// need to be `Send`, `Sync` or `'static`. Drop hooks can't receive a
+    /// context since they run whenever the last handle is dropped, possibly
+    /// after the cache is gone; they must own what they need.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is rejected, like
+    /// [`get_or_insert_with`](Self::get_or_insert_with).
+    pub fn get_or_insert_with_ctx<C: ?Sized, T: Send + Sync + 'static>(
+        &mut self,
+        key: &str,
+        ctx: &mut C,
+        f: impl FnOnce(&mut C) -> T,
+    ) -> Handle<T> {
+        self.get_or_insert_with(key, || f(ctx))
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but nothing is
+    /// inserted if `f` fails. A rejected key is returned as
+    /// [`CacheError::KeyTooLong`] without calling `f`.
+    pub fn get_or_try_insert_with<T: Send + Sync + 'static, E: From<CacheError>>(
+        &mut self,
+        key: &str,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> Result<Handle<T>, E> {
+        let key = self.check_key(key)?;
+        match self.get(key) {
+            Some(handle) => Ok(handle),
+            None => Ok(self.try_insert(key.to_owned(), f()?)?),
+        }
+    }
+
+    /// Returns the assets stored under `keys`, calling `loader` once with all
+    /// the keys that are missing (or hold a different type).
+    ///
+    /// Handles are returned in the order of `keys`. Keys that are neither
+    /// cached nor returned by `loader` are left out, so fewer handles than
+    /// keys may be returned. Entries returned by `loader` for keys that were
+    /// not asked for are inserted as well, but not returned. `loader` is not
+    /// called if every key is cached.
+    ///
+    /// Entries `loader` returns for keys that were already cached are
+    /// dropped, so the returned handles are always the cached values; only
+    /// missing keys holding a different type are replaced. If it returns
+    /// several entries under the same key, the first one wins and the others
+    /// are dropped.
+    ///
+    /// Keys rejected by [`set_max_key_len`](Self::set_max_key_len) are
+    /// skipped: they are not passed to `loader`, and entries it returns
+    /// under them are not inserted.
+    pub fn get_many_or_insert_with<T: Send + Sync + 'static>(
+        &mut self,
+        keys: &[&str],
+        loader: impl FnOnce(&[&str]) -> Vec<(String, T)>,
+    ) -> Vec<Handle<T>> {
+        let mut handles: Vec<Option<Handle<T>>> = keys.iter().map(|key| self.get(key)).collect();
+        let missing: Vec<&str> = keys
+            .iter()
+            .zip(&handles)
+            .filter(|(key, handle)| handle.is_none() && self.check_key(key).is_ok())
+            .map(|(key, _)| *key)
+            .collect();
+        if !missing.is_empty() {
+            let mut seen = HashSet::new();
+            for (key, value) in loader(&missing) {
+                let cached = !missing.contains(&key.as_str())
+                    && (self.in_use.contains_key(&key) || self.loaded.contains(&key));
+                if cached || !seen.insert(key.clone()) {
+                    continue;
+                }
+                let slots: Vec<_> = keys
+                    .iter()
+                    .zip(handles.iter_mut())
+                    .filter(|(k, slot)| slot.is_none() && **k == key)
+                    .map(|(_, slot)| slot)
+                    .collect();
+                let handle = match self.try_insert(key, value) {
+                    Ok(handle) => handle,
+                    Err(_) => continue,
+                };
+                for slot in slots {
+                    *slot = Some(handle.clone());
+                }
+            }
+        }
+        handles.into_iter().flatten().collect()
+    }
+
+    /// Returns the asset stored under `key`, taking it out of the cache when
+    /// possible.
+    ///
+    /// If the cache holds the only reference to the asset (no [`Handle`] or
+    /// [`RawHandle`] to it exists outside the cache) the entry is removed and
+    /// its value returned as [`Cow::Owned`] without cloning. Otherwise the
+    /// entry stays in its tier, marked as recently used, and a
+    /// [`Cow::Borrowed`] reference is returned. An asset of another type is
+    /// left untouched.
+    pub fn get_cow<T: Clone + Send + Sync + 'static>(&mut self, key: &str) -> Option<Cow<'_, T>> {
+        let key = self.check_key(key).ok()?;
+        let was_in_use = self.in_use.contains_key(key);
+        let raw = match self.in_use.get(key) {
+            Some(raw) => raw,
+            None => self.loaded.peek(key)?,
+        };
+        if !raw.0.value.is::<T>() {
+            return None;
+        }
+        if raw.is_unique() {
+            let raw = match was_in_use {
+                true => self.in_use.remove(key),
+                false => self.loaded.pop(key),
+            };
+            let raw = raw.unwrap();
+            let id = raw.id();
+            let value = match raw.into_value::<T>() {
+                Ok(value) => value,
+                Err(_) => unreachable!("type and references checked above"),
+            };
+            self.ids.remove(&id);
+            self.pins.remove(key);
+            if was_in_use {
+                self.notify_if_idle();
+            }
+            return Some(Cow::Owned(value));
+        }
+        let raw = match was_in_use {
+            true => {
+                let raw = &self.in_use[key];
+                self.clock += 1;
+                raw.0.last_access.store(self.clock, Ordering::Relaxed);
+                raw
+            }
+            false => self.loaded.get(key).unwrap(),
+        };
+        raw.0.value.downcast_ref().map(Cow::Borrowed)
+    }
+
+    /// Like [`get`](Self::get), but an asset of a different type is reported
+    /// as [`CacheError::WrongType`] instead of `None`, and a key rejected by
+    /// [`set_max_key_len`](Self::set_max_key_len) as
+    /// [`CacheError::KeyTooLong`].
+    pub fn try_get<T: Send + Sync + 'static>(
+        &mut self,
+        key: &str,
+    ) -> Result<Option<Handle<T>>, CacheError> {
+        let key = self.check_key(key)?;
+        match self.get_raw(key) {
+            Some(raw) => raw
+                .downcast()
+                .map(Some)
+                .map_err(|raw| CacheError::WrongType {
+                    key: key.to_owned(),
+                    expected: std::any::type_name::<T>(),
+                    found: raw.type_name(),
+                }),
+            None => Ok(None),
+        }
+    }
+
+    /// Like [`try_get`](Self::try_get), but a missing asset is reported as
+    /// [`CacheError::NotFound`], for resolving dependencies in loaders with
+    /// `?`.
+    pub fn require<T: Send + Sync + 'static>(
+        &mut self,
+        key: &str,
+    ) -> Result<Handle<T>, CacheError> {
+        self.try_get(key)?.ok_or_else(|| CacheError::NotFound {
+            key: key.to_owned(),
+        })
+    }
+
     pub fn get_raw(&mut self, key: &str) -> Option<RawHandle> {
-        match self.in_use.get(key) {
-            Some(value) => Some(value.clone()),
+        let key = self.check_key(key).ok()?;
+        self.lookup_raw(key)
+    }
+
+    // `get_raw` for a key that already passed `check_key`.
+    fn lookup_raw(&mut self, key: &str) -> Option<RawHandle> {
+        let value = match self.in_use.get(key) {
+            Some(value) => value.clone(),
             None => match self.loaded.pop(key) {
                 Some(value) => {
                     self.in_use.insert(key.to_owned(), value.clone());
-                    Some(value)
+                    value
+                }
+                None => {
+                    self.stats.misses += 1;
+                    self.tick_stats();
+                    return None;
                 }
-                None => None,
             },
+        };
+        self.mark_used(&value);
+        self.stats.hits += 1;
+        self.tick_stats();
+        Some(value)
+    }
+
+    /// Marks the entry under `key` as recently used without looking it up,
+    /// returning whether it exists.
+    ///
+    /// An entry in the loaded tier becomes the last to be evicted but, unlike
+    /// with [`get_raw`](Self::get_raw), stays in the loaded tier.
+    pub fn touch(&mut self, key: &str) -> bool {
+        let key = match self.check_key(key) {
+            Ok(key) => key,
+            Err(_) => return false,
+        };
+        if let Some(value) = self.in_use.get(key) {
+            let value = value.clone();
+            self.mark_used(&value);
+            return true;
         }
+        self.loaded.promote(key);
+        self.loaded.contains(key)
     }
 
-    pub fn remove(&mut self, value: RawHandle) {
-        // this value and one stored in in_use map
-        if Arc::strong_count(&value.0) == 2 {
-            self.in_use.remove(&value.0.key);
-            self.loaded.put(value.0.key.clone(), value);
+    /// [`touch`](Self::touch)es every key, e.g. the predicted next lookups,
+    /// returning how many exist. Later keys end up warmer than earlier ones.
+    pub fn touch_many(&mut self, keys: &[&str]) -> usize {
+        keys.iter().filter(|key| self.touch(key)).count()
+    }
+
+    /// Keys of all entries ordered from coldest to warmest, e.g. as a list of
+    /// candidates to [`unload`](Self::unload) under memory pressure.
+    ///
+    /// The loaded tier comes first, least recently used first, followed by
+    /// the in use tier ordered by when each entry was last inserted or looked
+    /// up.
+    pub fn unload_candidates(&self) -> Vec<&str> {
+        let mut in_use: Vec<_> = self.in_use.iter().collect();
+        in_use.sort_by_key(|(_, raw)| raw.0.last_access.load(Ordering::Relaxed));
+        self.loaded
+            .iter()
+            .rev()
+            .chain(in_use)
+            .map(|(key, _)| key.as_str())
+            .collect()
+    }
+
+    fn mark_used(&mut self, value: &RawHandle) {
+        self.clock += 1;
+        value.0.last_access.store(self.clock, Ordering::Relaxed);
+    }
+
+    /// Reloads every asset of type `T` in either tier for which `is_stale`
+    /// returns `true`, e.g. after source files changed, returning the keys
+    /// of the reloaded assets.
+    ///
+    /// Each asset is replaced in place: the new value gets a new handle under
+    /// the same key, in the same tier and at the same recency, so the sweep
+    /// never evicts anything. Handles to the old value keep seeing it and no
+    /// longer demote the entry; rebind users of in use assets with
+    /// [`get`](Self::get) and give the new handles back with
+    /// [`remove`](Self::remove).
+    pub fn revalidate<T: Send + Sync + 'static>(
+        &mut self,
+        mut is_stale: impl FnMut(&str, &T) -> bool,
+        mut reload: impl FnMut(&str) -> T,
+    ) -> Vec<String> {
+        let stale: Vec<String> = self
+            .in_use
+            .iter()
+            .chain(self.loaded.iter())
+            .filter(|(key, raw)| {
+                raw.0
+                    .value
+                    .downcast_ref()
+                    .is_some_and(|value| is_stale(key, value))
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &stale {
+            let drop_hook = self.drop_hooks.get(&TypeId::of::<T>()).cloned();
+            let new: RawHandle = Handle::new(key, drop_hook, reload(key)).into();
+            let old = match self.in_use.get_mut(key.as_str()) {
+                Some(slot) => slot,
+                None => self.loaded.peek_mut(key.as_str()).unwrap(),
+            };
+            let last_access = old.0.last_access.load(Ordering::Relaxed);
+            new.0.last_access.store(last_access, Ordering::Relaxed);
+            let old = std::mem::replace(old, new.clone());
+            self.ids.remove(&old.id());
+            self.track_id(&new);
         }
+        stale
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Returns a handle to every in use asset of type `T`, e.g. to rebind
+    /// them after a reload. Assets in the loaded tier are not included.
+    pub fn in_use_handles_of_type<T: Send + Sync + 'static>(&self) -> Vec<Handle<T>> {
+        self.in_use
+            .values()
+            .filter_map(|raw| raw.clone().downcast().ok())
+            .collect()
+    }
 
-    #[test]
-    fn create() {
-        let res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
-        assert!(res.in_use.is_empty());
-        assert!(res.loaded.is_empty());
+    /// Returns the asset stored under `key` without checking that it exists.
+    ///
+    /// Unlike [`get`](Self::get) this takes `&self`: the entry is not moved
+    /// to the in use tier, and an entry in the loaded tier keeps its recency
+    /// and can still be evicted while the returned handle is alive. The
+    /// lookup isn't counted in [`stats`](Self::stats) and doesn't update the
+    /// entry's last access either. The key length limit is applied as usual.
+    ///
+    /// The value is still type checked when the handle is dereferenced; the
+    /// savings over `get` are the tier move, the bookkeeping and the checks
+    /// of the lookup itself. The `get_unchecked` benchmark compares the two,
+    /// run it with `cargo bench --bench get_unchecked --features unstable`.
+    ///
+    /// # Safety
+    ///
+    /// [`get::<T>`](Self::get) must return `Some` for `key`: the key must be
+    /// accepted by the key length limit, an entry must be stored under it
+    /// (in either tier), and its value must be of type `T`.
+    #[cfg(feature = "unstable")]
+    pub unsafe fn get_unchecked<T: Send + Sync + 'static>(&self, key: &str) -> Handle<T> {
+        let key = match self.check_key(key) {
+            Ok(key) => key,
+            // SAFETY: the caller guarantees the key is accepted
+            Err(_) => unsafe { std::hint::unreachable_unchecked() },
+        };
+        let raw = match self.in_use.get(key) {
+            Some(raw) => raw,
+            // SAFETY: the caller guarantees the entry exists
+            None => unsafe { self.loaded.peek(key).unwrap_unchecked() },
+        };
+        Handle {
+            raw: raw.clone(),
+            ty: PhantomData,
+        }
     }
 
-    #[test]
-    fn insert_first() {
-        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
-        let _ = res.insert(String::from("test"), 1);
-        assert_eq!(res.in_use.len(), 1);
-        assert!(res.loaded.is_empty());
+    /// Iterates mutably over every asset of type `T` in either tier.
+    ///
+    /// Only assets the cache holds the only reference to are yielded; assets
+    /// with a [`Handle`] or [`RawHandle`] alive elsewhere are silently
+    /// skipped, as are assets of other types.
+    pub fn iter_mut<T: Send + Sync + 'static>(&mut self) -> impl Iterator<Item = (&str, &mut T)> {
+        self.in_use
+            .iter_mut()
+            .chain(self.loaded.iter_mut())
+            .filter_map(|(key, raw)| {
+                // `get_mut` acquires the count, so a pin by a handle dropped
+                // on another thread is visible here
+                let inner = Arc::get_mut(&mut raw.0)?;
+                if *inner.address_pinned.get_mut() {
+                    return None;
+                }
+                Some((key.as_str(), inner.value.downcast_mut()?))
+            })
     }
 
-    #[test]
-    fn insert_deref() {
-        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
-        let asset = res.insert(String::from("test"), 1);
-        assert_eq!(*asset, 1);
+    /// Keeps a map from [`Handle::id`] to key for every entry, so assets can
+    /// be looked up with [`get_by_id`](Self::get_by_id). Off by default,
+    /// since it costs a map insert and removal per entry.
+    ///
+    /// Enabling it indexes the entries already cached; disabling it drops
+    /// the map.
+    pub fn set_id_lookup(&mut self, enabled: bool) {
+        self.id_lookup = enabled;
+        self.ids = match enabled {
+            true => self
+                .in_use
+                .values()
+                .chain(self.loaded.iter().map(|(_, raw)| raw))
+                .map(|raw| (raw.id(), raw.key_arc()))
+                .collect(),
+            false => HashMap::new(),
+        };
     }
 
-    #[test]
-    fn insert_no_extra_clones() {
-        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
-        let asset = res.insert(String::from("test"), 1);
-        assert_eq!(Arc::strong_count(&asset.raw.0), 2);
-        assert_eq!(Arc::weak_count(&asset.raw.0), 0);
+    fn track_id(&mut self, raw: &RawHandle) {
+        if self.id_lookup {
+            self.ids.insert(raw.id(), raw.key_arc());
+        }
     }
 
-    #[test]
-    fn insert_twice() {
-        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
-        let _ = res.insert(String::from("test"), 1);
-        let _ = res.insert(String::from("test2"), 2);
-        assert_eq!(res.in_use.len(), 2);
-        assert!(res.loaded.is_empty());
+    /// Returns the asset with the given [`Handle::id`], if it is still
+    /// cached. Always `None` unless enabled with
+    /// [`set_id_lookup`](Self::set_id_lookup).
+    pub fn get_by_id(&mut self, id: u64) -> Option<RawHandle> {
+        // looked up as stored, since the key length limit may have been set
+        // after the entry was inserted
+        let key = self.ids.get(&id)?.clone();
+        let raw = self.in_use.get(&*key).or_else(|| self.loaded.peek(&*key))?;
+        if raw.id() != id {
+            return None;
+        }
+        self.lookup_raw(&key)
     }
 
-    #[test]
-    fn insert_and_get() {
-        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
-        let asset = res.insert(String::from("test"), 1);
-        let asset2 = res.get_raw("test").unwrap();
-        assert!(asset.raw.ptr_eq(&asset2));
+    pub fn remove(&mut self, value: RawHandle) {
+        // a stale handle to an overwritten entry must not demote the new one
+        let cached = match self.in_use.get(value.key()) {
+            Some(cached) => cached.ptr_eq(&value),
+            None => false,
+        };
+        // this value, one stored in in_use map and the caller's own clones
+        let users = Arc::strong_count(&value.0) - value.0.borrows.load(Ordering::Relaxed);
+        if cached && users == 2 + self.extra_refs {
+            self.make_room();
+            if let Some((key, _)) = self.in_use.remove_entry(value.key()) {
+                self.demote(key, value);
+                self.notify_if_idle();
+            }
+        }
     }
 
-    #[test]
-    fn insert_and_overwrite() {
-        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
-        let asset1 = res.insert(String::from("test"), 1);
-        let asset2 = res.insert(String::from("test"), 2);
-        let asset3 = res.get_raw("test").unwrap();
-        assert_eq!(*asset1, 1);
-        assert_eq!(*asset2, 2);
-        assert!(asset2.raw.ptr_eq(&asset3));
+    /// Makes [`remove`](Self::remove) demote entries while `extra_refs`
+    /// other handles to them are still alive, for layers that keep a clone
+    /// of every handle for their own bookkeeping.
+    ///
+    /// This is fragile: the count has to hold for every entry, an entry with
+    /// fewer clones is never demoted, and one with more is demoted while a
+    /// user still holds it. Only `remove` is affected; entries added by
+    /// [`extend`](Extend::extend) still go to the loaded tier only if the
+    /// cache holds their only handle.
+    pub fn set_remove_baseline(&mut self, extra_refs: usize) {
+        self.extra_refs = extra_refs;
     }
 
-    #[test]
-    fn remove() {
-        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
-        let asset1 = res.insert(String::from("test"), 1);
-        res.remove(asset1.raw);
-        assert_eq!(res.in_use.len(), 0);
-        assert_eq!(res.loaded.len(), 1);
+    /// Removes the entry under `key` from the cache, whichever tier it is in.
+    ///
+    /// The key is returned along with the handle so its allocation can be
+    /// reused for a later [`insert`](Self::insert).
+    pub fn unload(&mut self, key: &str) -> Option<(String, RawHandle)> {
+        let key = self.check_key(key).ok()?;
+        let (entry, was_in_use) = match self.in_use.remove_entry(key) {
+            Some(entry) => (entry, true),
+            None => (self.loaded.pop_entry(key)?, false),
+        };
+        self.ids.remove(&entry.1.id());
+        self.pins.remove(key);
+        // last, so a panicking callback leaves the cache consistent
+        if was_in_use {
+            self.notify_if_idle();
+        }
+        Some(entry)
     }
 
-    #[test]
-    fn remove_get() {
-        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
-        let asset1 = res.insert(String::from("test"), 1);
-        res.remove(asset1.raw);
-        assert_eq!(res.in_use.len(), 0);
-        assert_eq!(res.loaded.len(), 1);
-        assert!(res.get_raw("test").is_some());
-        assert_eq!(res.in_use.len(), 1);
-        assert_eq!(res.loaded.len(), 0);
+    /// Sets a callback called whenever the in use tier becomes empty, i.e.
+    /// the last in use entry was demoted or taken out of the cache. It is not
+    /// called again until an entry has been in use in between.
+    pub fn set_idle_callback(&mut self, f: impl FnMut() + Send + 'static) {
+        self.idle_callback = Some(Box::new(f));
     }
 
-    #[test]
-    fn remove_multiple() {
-        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
-        let asset1 = res.insert(String::from("test"), 1);
+    // Call after removing an entry from `in_use`.
+    fn notify_if_idle(&mut self) {
+        if self.in_use.is_empty() {
+            if let Some(callback) = &mut self.idle_callback {
+                callback();
+            }
+        }
+    }
+
+    /// Number of entries the loaded tier can take before demotions (see
+    /// [`remove`](Self::remove)) start evicting.
+    pub fn capacity_remaining(&self) -> usize {
+        self.loaded.cap().get() - self.loaded.len()
+    }
+
+    /// Whether the next demotion would evict an entry from the loaded tier.
+    ///
+    /// [`insert`](Self::insert) never evicts since new entries start out in
+    /// use; entries are only evicted when a demotion finds the loaded tier
+    /// full.
+    pub fn would_evict(&self) -> bool {
+        self.loaded_at_capacity()
+    }
+
+    /// Whether the loaded tier holds as many entries as its capacity.
+    pub fn loaded_at_capacity(&self) -> bool {
+        self.loaded.len() >= self.loaded.cap().get()
+    }
+
+    /// Changes the capacity of the loaded tier, evicting entries if it holds
+    /// more than `capacity`.
+    ///
+    /// Watermarks set with [`set_watermarks`](Self::set_watermarks) use
+    /// `capacity` as the new high watermark, and are removed if the low
+    /// watermark is not below it.
+    pub fn set_capacity(&mut self, capacity: NonZeroUsize) {
+        while self.loaded.len() > capacity.get() {
+            self.evict_one();
+        }
+        self.loaded.resize(capacity);
+        if let Some((low, _)) = self.watermarks {
+            self.watermarks = (low < capacity.get()).then_some((low, capacity.get()));
+        }
+    }
+
+    /// Changes the capacity until the returned guard is dropped, which
+    /// restores the current capacity and watermarks, evicting down to them.
+    ///
+    /// The cache is used through the guard in the meantime. Calls to
+    /// [`set_capacity`](Self::set_capacity) or
+    /// [`set_watermarks`](Self::set_watermarks) made through it only last
+    /// until the guard is dropped.
+    pub fn with_temporary_capacity(&mut self, capacity: NonZeroUsize) -> TemporaryCapacity<'_> {
+        let saved_capacity = self.loaded.cap();
+        let saved_watermarks = self.watermarks;
+        self.set_capacity(capacity);
+        TemporaryCapacity {
+            cache: self,
+            saved_capacity,
+            saved_watermarks,
+        }
+    }
+
+    /// Rebuilds the in use map into a fresh table sized for its current
+    /// entries, dropping the tombstones and spare capacity left by churn.
+    ///
+    /// Unlike `shrink_to_fit`, the map is rebuilt even when its capacity
+    /// wouldn't shrink, so tombstones are always dropped. The `compact`
+    /// benchmark (`cargo bench --bench compact`) compares lookups in a
+    /// churned map before and after.
+    ///
+    /// Entries and handles are unaffected. This rehashes every key, so it is
+    /// meant for occasional maintenance of long lived caches, not per frame.
+    pub fn compact(&mut self) {
+        let mut in_use =
+            HashMap::with_capacity_and_hasher(self.in_use.len(), self.in_use.hasher().clone());
+        in_use.extend(self.in_use.drain());
+        self.in_use = in_use;
+        #[cfg(feature = "alloc-telemetry")]
+        {
+            self.in_use_capacity = self.in_use.capacity();
+        }
+    }
+
+    /// Evicts entries from the loaded tier until it holds at most `len`,
+    /// e.g. under memory pressure, returning them in eviction order.
+    ///
+    /// Unlike [`set_capacity`](Self::set_capacity) the capacity stays the
+    /// same, so the loaded tier fills up again as entries are demoted.
+    pub fn shrink_loaded_to(&mut self, len: usize) -> Vec<RawHandle> {
+        let mut evicted = Vec::new();
+        while self.loaded.len() > len {
+            match self.evict_one() {
+                Some(raw) => evicted.push(raw),
+                None => break,
+            }
+        }
+        evicted
+    }
+
+    /// Evicts in batches instead of one entry per demotion.
+    ///
+    /// `loaded` grows up to `high` entries (replacing the capacity given to
+    /// [`new`](Self::new)). When a demotion finds it full, the least recently
+    /// used entries are evicted until only `low` remain, and the demoted entry
+    /// is added after that.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `low >= high`.
+    pub fn set_watermarks(&mut self, low: usize, high: NonZeroUsize) {
+        assert!(
+            low < high.get(),
+            "low watermark must be below high watermark"
+        );
+        self.watermarks = Some((low, high.get()));
+        self.set_capacity(high);
+    }
+
+    // Adds an existing handle, replacing any entry with the same key.
+    // Handles keep the key they were created with, so a key the limit would
+    // truncate is rejected rather than stored where lookups can't reach it.
+    fn check_handle_key(&self, value: &RawHandle) -> Result<(), CacheError> {
+        match self.max_key_len {
+            Some((max, _)) if value.key().len() > max => Err(CacheError::KeyTooLong {
+                len: value.key().len(),
+                max,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn insert_raw(&mut self, value: RawHandle) -> Result<(), CacheError> {
+        self.check_handle_key(&value)?;
+        let key = value.key().to_owned();
+        if let Some(old) = self.loaded.pop(&key) {
+            self.ids.remove(&old.id());
+        }
+        // only referenced by us, so nothing would ever demote it
+        let demoted = Arc::strong_count(&value.0) == 1;
+        if demoted {
+            self.make_room();
+        }
+        let was_in_use = match self.in_use.remove(&key) {
+            Some(old) => {
+                self.ids.remove(&old.id());
+                true
+            }
+            None => false,
+        };
+        self.track_id(&value);
+        if demoted {
+            self.demote(key, value);
+            if was_in_use {
+                self.notify_if_idle();
+            }
+        } else {
+            self.mark_used(&value);
+            self.in_use.insert(key, value);
+        }
+        self.stats.inserts += 1;
+        self.tick_stats();
+        Ok(())
+    }
+
+    /// Pins the entry under `key` so it is not evicted while unpinned entries
+    /// can be evicted instead. Returns `false` if there is no such entry.
+    ///
+    /// When every entry in the loaded tier is pinned, the one with the lowest
+    /// `priority` (the least recently used among equals) is unpinned and
+    /// evicted, so pinning can't make the cache grow past its capacity.
+    /// Pinning again changes the priority.
+    pub fn pin_with_priority(&mut self, key: &str, priority: u8) -> bool {
+        let key = match self.check_key(key) {
+            Ok(key) => key,
+            Err(_) => return false,
+        };
+        if !self.in_use.contains_key(key) && !self.loaded.contains(key) {
+            return false;
+        }
+        self.pins.insert(key.to_owned(), priority);
+        true
+    }
+
+    /// Removes the pin from `key`, returning whether it was pinned.
+    pub fn unpin(&mut self, key: &str) -> bool {
+        match self.check_key(key) {
+            Ok(key) => self.pins.remove(key).is_some(),
+            Err(_) => false,
+        }
+    }
+
+    // Evicts so the next demotion fits. This runs the evict callback, so it
+    // must be called before the demoted entry is taken out of `in_use`: if
+    // the callback panics, every entry is still in one of the tiers.
+    fn make_room(&mut self) {
+        if self.loaded_at_capacity() {
+            let low = match self.watermarks {
+                Some((low, _)) => low,
+                None => self.loaded.cap().get() - 1,
+            };
+            while self.loaded.len() > low {
+                self.evict_one();
+            }
+        }
+    }
+
+    // Call `make_room` first.
+    fn demote(&mut self, key: String, value: RawHandle) {
+        if let Some((_, evicted)) = self.loaded.push(key, value) {
+            self.ids.remove(&evicted.id());
+        }
+    }
+
+    /// Protects entries inserted less than `min_lifetime` ago from eviction,
+    /// so a burst of prefetches doesn't evict entries it just added.
+    ///
+    /// Older entries are evicted first, least recently used first. If every
+    /// unpinned entry is too young, the oldest of them is evicted anyway,
+    /// regardless of recency; entries created at the same instant go in the
+    /// order they were created in, e.g. the order of a batch. Pinned entries
+    /// are only evicted after all unpinned ones, see
+    /// [`pin_with_priority`](Self::pin_with_priority).
+    ///
+    /// `Duration::ZERO` (the default) turns this off.
+    pub fn set_min_lifetime(&mut self, min_lifetime: Duration) {
+        self.min_lifetime = min_lifetime;
+    }
+
+    /// Sets a callback asked before an entry is evicted from the loaded tier,
+    /// which can keep it by returning [`EvictDecision::Keep`].
+    ///
+    /// Each entry is offered at most once per eviction; if the callback keeps
+    /// every entry, the next victim is evicted without asking.
+    pub fn set_evict_callback(
+        &mut self,
+        f: impl FnMut(&str, &RawHandle) -> EvictDecision + Send + 'static,
+    ) {
+        self.evict_callback = Some(Box::new(f));
+    }
+
+    fn evict_one(&mut self) -> Option<RawHandle> {
+        let mut offered = HashSet::new();
+        let mut key = self.pick_victim(&offered)?;
+        // taken out so `pick_victim` can borrow self
+        if let Some(callback) = self.evict_callback.take() {
+            let mut guard = RestoreEvictCallback {
+                cache: &mut *self,
+                callback: Some(callback),
+            };
+            let callback = guard.callback.as_mut().unwrap();
+            loop {
+                let value = guard.cache.loaded.peek(&key).unwrap();
+                if callback(&key, value) == EvictDecision::Evict {
+                    break;
+                }
+                guard.cache.loaded.promote(&key);
+                offered.insert(key);
+                key = match guard.cache.pick_victim(&offered) {
+                    Some(key) => key,
+                    // everything was kept
+                    None => guard.cache.pick_victim(&HashSet::new()).unwrap(),
+                };
+                if offered.contains(&key) {
+                    break;
+                }
+            }
+        }
+        let evicted = self.loaded.pop(&key)?;
+        self.ids.remove(&evicted.id());
+        self.pins.remove(&key);
+        self.stats.evictions += 1;
+        Some(evicted)
+    }
+
+    // Least recently used unpinned entry older than `min_lifetime`, else the
+    // oldest unpinned one by creation time, ties broken by id (creation
+    // order, since `Instant`s can be equal), else the lowest priority pinned
+    // one. Entries in `skip` (already offered to the evict callback) are
+    // never picked.
+    fn pick_victim(&self, skip: &HashSet<String>) -> Option<String> {
+        let mut young: Option<(&String, (Instant, u64))> = None;
+        let mut pinned: Option<(&String, u8)> = None;
+        for (key, raw) in self.loaded.iter().rev() {
+            if skip.contains(key) {
+                continue;
+            }
+            match self.pins.get(key) {
+                None => {
+                    if raw.0.created.elapsed() >= self.min_lifetime {
+                        return Some(key.clone());
+                    }
+                    let age = (raw.0.created, raw.id());
+                    if young.is_none_or(|(_, oldest)| age < oldest) {
+                        young = Some((key, age));
+                    }
+                }
+                Some(&priority) => {
+                    if pinned.is_none_or(|(_, lowest)| priority < lowest) {
+                        pinned = Some((key, priority));
+                    }
+                }
+            }
+        }
+        young
+            .map(|(key, _)| key)
+            .or(pinned.map(|(key, _)| key))
+            .cloned()
+    }
+}
+
+// Puts the evict callback back even if it panics.
+struct RestoreEvictCallback<'a> {
+    cache: &'a mut ResourceCache,
+    callback: Option<EvictCallback>,
+}
+
+impl Drop for RestoreEvictCallback<'_> {
+    fn drop(&mut self) {
+        self.cache.evict_callback = self.callback.take();
+    }
+}
+
+/// Guard returned by [`ResourceCache::with_temporary_capacity`].
+pub struct TemporaryCapacity<'a> {
+    cache: &'a mut ResourceCache,
+    saved_capacity: NonZeroUsize,
+    saved_watermarks: Option<(usize, usize)>,
+}
+
+impl Deref for TemporaryCapacity<'_> {
+    type Target = ResourceCache;
+
+    fn deref(&self) -> &Self::Target {
+        self.cache
+    }
+}
+
+impl DerefMut for TemporaryCapacity<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.cache
+    }
+}
+
+impl Drop for TemporaryCapacity<'_> {
+    fn drop(&mut self) {
+        self.cache.watermarks = self.saved_watermarks;
+        self.cache.set_capacity(self.saved_capacity);
+    }
+}
+
+// Longest prefix of `s` that is at most `max` bytes.
+fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+impl Default for ResourceCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+/// Adds handles under the key they were created with, the paired key is not
+/// used. Handles referenced elsewhere go to the in use tier, the rest are
+/// treated as recently removed, evicting if the loaded tier is full.
+/// Handles with keys longer than [`ResourceCache::set_max_key_len`] allows
+/// are skipped, whatever the [`KeyLengthPolicy`].
+impl Extend<(String, RawHandle)> for ResourceCache {
+    fn extend<I: IntoIterator<Item = (String, RawHandle)>>(&mut self, iter: I) {
+        for (_, value) in iter {
+            let _ = self.insert_raw(value);
+        }
+    }
+}
+
+/// Collects into a cache with [`DEFAULT_CAPACITY`], see the [`Extend`] impl.
+/// Use [`ResourceCache::new`] and [`extend`](Extend::extend) for a different
+/// capacity.
+impl FromIterator<(String, RawHandle)> for ResourceCache {
+    fn from_iter<I: IntoIterator<Item = (String, RawHandle)>>(iter: I) -> Self {
+        let mut res = Self::default();
+        res.extend(iter);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create() {
+        let res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        assert!(res.in_use.is_empty());
+        assert!(res.loaded.is_empty());
+    }
+
+    #[test]
+    fn seeded_hasher() {
+        let keys = |seed| {
+            let mut res = ResourceCache::with_seeded_hasher(NonZeroUsize::new(2).unwrap(), seed);
+            for i in 0..20 {
+                let _ = res.insert(format!("test{}", i), i);
+            }
+            res.in_use.keys().cloned().collect::<Vec<_>>()
+        };
+        assert_eq!(keys(1), keys(1));
+        assert_ne!(keys(1), keys(2));
+    }
+
+    #[test]
+    fn insert_first() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let _ = res.insert(String::from("test"), 1);
+        assert_eq!(res.in_use.len(), 1);
+        assert!(res.loaded.is_empty());
+    }
+
+    #[test]
+    fn insert_deref() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), 1);
+        assert_eq!(*asset, 1);
+    }
+
+    #[test]
+    fn insert_value() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), vec![1, 2]);
+        assert_eq!(asset.value(), &[1, 2]);
+    }
+
+    #[test]
+    fn into_pin() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), String::from("value"));
+        let addr = asset.value() as *const String;
+        let pinned = asset.into_pin();
+        let value: Pin<&String> = pinned.as_ref();
+        assert_eq!(*value, "value");
+        drop(pinned);
+        // neither moved out nor mutated after the pinned handle is gone
+        assert_eq!(res.iter_mut::<String>().count(), 0);
+        assert!(matches!(
+            res.get_cow::<String>("test"),
+            Some(Cow::Borrowed(_))
+        ));
+        let asset = res.get::<String>("test").unwrap();
+        assert_eq!(asset.value() as *const String, addr);
+    }
+
+    #[test]
+    fn insert_no_extra_clones() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), 1);
+        assert_eq!(Arc::strong_count(&asset.raw.0), 2);
+        assert_eq!(Arc::weak_count(&asset.raw.0), 0);
+    }
+
+    #[test]
+    fn key_arc() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), 1);
+        assert_eq!(asset.key(), "test");
+        let key1 = asset.key_arc();
+        let key2 = res.get_raw("test").unwrap().key_arc();
+        assert_eq!(&*key1, "test");
+        assert!(Arc::ptr_eq(&key1, &key2));
+    }
+
+    #[test]
+    fn weak_count() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), 1);
+        assert_eq!(asset.weak_count(), 0);
+        let weak = Arc::downgrade(&asset.raw.0);
+        assert_eq!(asset.weak_count(), 1);
+        assert_eq!(res.get_raw("test").unwrap().weak_count(), 1);
+        drop(weak);
+        assert_eq!(asset.weak_count(), 0);
+    }
+
+    #[test]
+    fn insert_twice() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let _ = res.insert(String::from("test"), 1);
+        let _ = res.insert(String::from("test2"), 2);
+        assert_eq!(res.in_use.len(), 2);
+        assert!(res.loaded.is_empty());
+    }
+
+    #[test]
+    fn insert_and_get() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), 1);
+        let asset2 = res.get_raw("test").unwrap();
+        assert!(asset.raw.ptr_eq(&asset2));
+    }
+
+    #[test]
+    fn insert_and_overwrite() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test"), 2);
+        let asset3 = res.get_raw("test").unwrap();
+        assert_eq!(*asset1, 1);
+        assert_eq!(*asset2, 2);
+        assert!(asset2.raw.ptr_eq(&asset3));
+    }
+
+    #[test]
+    fn max_key_len_reject() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_max_key_len(Some(4), KeyLengthPolicy::Reject);
+        assert_eq!(
+            res.try_insert(String::from("test2"), 2).unwrap_err(),
+            CacheError::KeyTooLong { len: 5, max: 4 }
+        );
+        assert!(res.in_use.is_empty());
+        assert!(res.get_raw("test2").is_none());
+        assert_eq!(*res.try_insert(String::from("test"), 1).unwrap(), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn max_key_len_reject_insert() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_max_key_len(Some(4), KeyLengthPolicy::Reject);
+        let _ = res.insert(String::from("test2"), 2);
+    }
+
+    #[test]
+    fn max_key_len_truncate() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_max_key_len(Some(5), KeyLengthPolicy::Truncate);
+        let asset = res.insert(String::from("test-long"), 1);
+        assert_eq!(asset.key(), "test-");
+        assert!(res.get_raw("test-long").unwrap().ptr_eq(&asset.raw));
+        assert!(res.get_raw("test-other").unwrap().ptr_eq(&asset.raw));
+        // not cut inside a character
+        let asset = res.insert(String::from("testé"), 2);
+        assert_eq!(asset.key(), "test");
+    }
+
+    #[test]
+    fn insert_if_changed() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let (asset1, inserted) = res.insert_if_changed(String::from("test"), 1);
+        assert!(inserted);
+        let (asset2, inserted) = res.insert_if_changed(String::from("test"), 1);
+        assert!(!inserted);
+        assert!(asset1.raw.ptr_eq(&asset2.raw));
+        let (asset3, inserted) = res.insert_if_changed(String::from("test"), 2);
+        assert!(inserted);
+        assert!(!asset1.raw.ptr_eq(&asset3.raw));
+        assert_eq!(*res.get::<i32>("test").unwrap(), 2);
+    }
+
+    #[test]
+    fn insert_if_changed_other_type() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), 1u32);
+        res.remove(asset.raw);
+        let (_, inserted) = res.insert_if_changed(String::from("test"), 1i32);
+        assert!(inserted);
+        assert!(res.get::<u32>("test").is_none());
+    }
+
+    #[test]
+    fn remove() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        res.remove(asset1.raw);
+        assert_eq!(res.in_use.len(), 0);
+        assert_eq!(res.loaded.len(), 1);
+    }
+
+    #[test]
+    fn idle_callback() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let idle = Arc::new(AtomicUsize::new(0));
+        let mut res = ResourceCache::new(NonZeroUsize::new(3).unwrap());
+        let i = idle.clone();
+        res.set_idle_callback(move || {
+            i.fetch_add(1, Ordering::SeqCst);
+        });
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test2"), 2);
+        res.remove(asset1.raw);
+        assert_eq!(idle.load(Ordering::SeqCst), 0);
+        res.remove(asset2.raw);
+        assert_eq!(idle.load(Ordering::SeqCst), 1);
+        // already empty
+        res.extend([(String::from("test3"), Handle::new("test3", None, 3).raw)]);
+        assert_eq!(idle.load(Ordering::SeqCst), 1);
+        let asset1 = res.get_raw("test").unwrap();
+        assert!(res.get_cow::<i32>("test2").is_some());
+        assert_eq!(idle.load(Ordering::SeqCst), 1);
+        res.remove(asset1);
+        assert_eq!(idle.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn unload_idle_callback_panic() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        res.set_idle_callback(|| panic!("idle"));
+        let _asset = res.insert(String::from("test"), 1);
+        res.pin_with_priority("test", 0);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            res.unload("test");
+        }));
+        assert!(result.is_err());
+        assert!(res.ids.is_empty());
+        assert!(res.pins.is_empty());
+        check_invariants(&res);
+    }
+
+    #[test]
+    fn unload() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test2"), 2);
+        res.remove(asset2.raw);
+        let (key, raw) = res.unload("test").unwrap();
+        assert_eq!(key, "test");
+        assert!(raw.ptr_eq(&asset1.raw));
+        let (key, _) = res.unload("test2").unwrap();
+        assert!(res.unload("test2").is_none());
+        assert!(res.in_use.is_empty());
+        assert!(res.loaded.is_empty());
+        assert!(res.ids.is_empty());
+        let _ = res.insert(key, 3);
+        assert_eq!(*res.get::<i32>("test2").unwrap(), 3);
+    }
+
+    #[test]
+    fn remove_stale() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        // two references, like a current entry with no outside handles
+        let _asset1_clone = asset1.clone();
+        let _asset2 = res.insert(String::from("test"), 2);
+        res.remove(asset1.raw);
+        assert_eq!(res.in_use.len(), 1);
+        assert!(res.loaded.is_empty());
+        assert_eq!(*res.get::<i32>("test").unwrap(), 2);
+    }
+
+    #[test]
+    fn remove_get() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        res.remove(asset1.raw);
+        assert_eq!(res.in_use.len(), 0);
+        assert_eq!(res.loaded.len(), 1);
+        assert!(res.get_raw("test").is_some());
+        assert_eq!(res.in_use.len(), 1);
+        assert_eq!(res.loaded.len(), 0);
+    }
+
+    #[test]
+    fn remove_multiple() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
         let asset2 = res.insert(String::from("test2"), 2);
         let asset3 = res.insert(String::from("test3"), 3);
         res.remove(asset1.raw);
@@ -226,9 +1859,1309 @@ mod tests {
         res.remove(asset1.raw);
         assert_eq!(res.in_use.len(), 0);
         assert_eq!(res.loaded.len(), 1);
-        let asset2 = res.insert(String::from("test"), 3);
+        let _asset2 = res.insert(String::from("test"), 3);
         assert_eq!(res.in_use.len(), 1);
         assert_eq!(res.loaded.len(), 0);
         assert_eq!(*res.get::<i32>("test").unwrap(), 3);
     }
+
+    #[test]
+    fn ids_unique() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test2"), 2);
+        let asset3 = res.insert(String::from("test"), 3);
+        assert_ne!(asset1.id(), asset2.id());
+        assert_ne!(asset1.id(), asset3.id());
+        assert_ne!(asset2.id(), asset3.id());
+    }
+
+    #[test]
+    fn in_use_handles_of_type() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        let _ = res.insert(String::from("test2"), 2);
+        let _ = res.insert(String::from("test3"), String::from("3"));
+        let asset4 = res.insert(String::from("test4"), 4);
+        res.remove(asset4.raw);
+        let mut handles = res.in_use_handles_of_type::<i32>();
+        handles.sort_by_key(|handle| **handle);
+        assert_eq!(handles.len(), 2);
+        assert!(handles[0].raw.ptr_eq(&asset1.raw));
+        assert_eq!(*handles[1], 2);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn get_unchecked() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test2"), 2);
+        res.remove(asset2.raw);
+        unsafe {
+            assert!(res.get_unchecked::<i32>("test").raw.ptr_eq(&asset1.raw));
+            assert_eq!(*res.get_unchecked::<i32>("test2"), 2);
+        }
+        assert_eq!(res.loaded.len(), 1);
+        res.set_max_key_len(Some(4), KeyLengthPolicy::Truncate);
+        unsafe {
+            assert!(res.get_unchecked::<i32>("test2").raw.ptr_eq(&asset1.raw));
+        }
+    }
+
+    trait Widget {
+        fn name(&self) -> String;
+    }
+
+    struct Button(i32);
+
+    impl Widget for Button {
+        fn name(&self) -> String {
+            format!("button {}", self.0)
+        }
+    }
+
+    struct Label(&'static str);
+
+    impl Widget for Label {
+        fn name(&self) -> String {
+            format!("label {}", self.0)
+        }
+    }
+
+    #[test]
+    fn get_as() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.register_coercion::<Button, dyn Widget>(|c| c);
+        res.register_coercion::<Label, dyn Widget>(|c| c);
+        let button = res.insert(String::from("button"), Button(1));
+        let _ = res.insert(String::from("label"), Label("a"));
+        let _ = res.insert(String::from("int"), 1);
+        let widget = res.get_as::<dyn Widget>("button").unwrap();
+        assert_eq!(widget.name(), "button 1");
+        assert!(widget.raw.ptr_eq(&button.raw));
+        assert_eq!(res.get_as::<dyn Widget>("label").unwrap().name(), "label a");
+        assert!(res.get_as::<dyn Widget>("int").is_none());
+        assert!(res.get_as::<dyn Widget>("missing").is_none());
+        assert!(res.get_as::<dyn std::fmt::Debug>("button").is_none());
+    }
+
+    #[test]
+    fn iter_mut() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test2"), 2);
+        let _ = res.insert(String::from("test3"), 3);
+        let _ = res.insert(String::from("test4"), String::from("4"));
+        res.remove(asset2.raw);
+        let mut keys: Vec<&str> = res
+            .iter_mut::<i32>()
+            .map(|(key, value)| {
+                *value *= 10;
+                key
+            })
+            .collect();
+        keys.sort();
+        assert_eq!(keys, ["test2", "test3"]);
+        assert_eq!(*asset1, 1);
+        assert_eq!(*res.get::<i32>("test2").unwrap(), 20);
+        assert_eq!(*res.get::<i32>("test3").unwrap(), 30);
+    }
+
+    #[test]
+    fn try_get() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let _ = res.insert(String::from("test"), 1);
+        assert_eq!(*res.try_get::<i32>("test").unwrap().unwrap(), 1);
+        assert!(res.try_get::<i32>("missing").unwrap().is_none());
+        let err = res.try_get::<String>("test").unwrap_err();
+        assert_eq!(
+            err,
+            CacheError::WrongType {
+                key: String::from("test"),
+                expected: std::any::type_name::<String>(),
+                found: std::any::type_name::<i32>(),
+            }
+        );
+        assert!(err.to_string().starts_with("asset \"test\" is not of type"));
+    }
+
+    #[test]
+    fn require() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let _ = res.insert(String::from("test"), 1);
+        assert_eq!(*res.require::<i32>("test").unwrap(), 1);
+        let err = res.require::<i32>("missing").unwrap_err();
+        assert_eq!(
+            err,
+            CacheError::NotFound {
+                key: String::from("missing")
+            }
+        );
+        assert_eq!(err.to_string(), "asset \"missing\" not found");
+        assert!(matches!(
+            res.require::<String>("test"),
+            Err(CacheError::WrongType { .. })
+        ));
+        res.set_max_key_len(Some(4), KeyLengthPolicy::Reject);
+        assert!(matches!(
+            res.require::<i32>("missing"),
+            Err(CacheError::KeyTooLong { len: 7, max: 4 })
+        ));
+        assert!(matches!(
+            res.try_get::<i32>("missing"),
+            Err(CacheError::KeyTooLong { len: 7, max: 4 })
+        ));
+    }
+
+    trait Source: Send + Sync {
+        fn sample(&self) -> i32;
+    }
+
+    struct Sine;
+
+    impl Source for Sine {
+        fn sample(&self) -> i32 {
+            7
+        }
+    }
+
+    #[test]
+    fn boxed_trait_object() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let boxed: Box<dyn Source> = Box::new(Sine);
+        let asset = res.insert(String::from("test"), boxed);
+        res.remove(asset.raw);
+        // `dyn Source` already implies `Send + Sync`, but the spelling matters
+        assert!(res.get::<Box<dyn Source + Send + Sync>>("test").is_none());
+        assert!(res.get::<Box<Sine>>("test").is_none());
+        assert!(res.get::<Sine>("test").is_none());
+        let asset = res.get::<Box<dyn Source>>("test").unwrap();
+        assert_eq!(asset.sample(), 7);
+        assert_eq!(
+            asset.raw.type_name(),
+            std::any::type_name::<Box<dyn Source>>()
+        );
+    }
+
+    #[test]
+    fn boxed_without_annotation() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let _asset = res.insert(String::from("test"), Box::new(Sine));
+        match res.try_get::<Box<dyn Source>>("test") {
+            Err(CacheError::WrongType { found, .. }) => {
+                assert_eq!(found, std::any::type_name::<Box<Sine>>())
+            }
+            _ => panic!("expected a type mismatch"),
+        }
+    }
+
+    #[test]
+    fn unload_candidates() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(3).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test2"), 2);
+        let _ = res.insert(String::from("test3"), 3);
+        let _ = res.insert(String::from("test4"), 4);
+        let _ = res.insert(String::from("test5"), 5);
+        res.remove(asset2.raw);
+        res.remove(asset1.raw);
+        let _ = res.get_raw("test3");
+        assert_eq!(
+            res.unload_candidates(),
+            ["test2", "test", "test4", "test5", "test3"]
+        );
+    }
+
+    #[test]
+    fn get_by_id() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        let asset = res.insert(String::from("test"), 1);
+        let id = asset.id();
+        assert!(asset.raw.ptr_eq(&res.get_by_id(id).unwrap()));
+        // no second copy of the key
+        assert!(Arc::ptr_eq(&res.ids[&id], &asset.key_arc()));
+        res.remove(asset.raw);
+        assert_eq!(*res.get_by_id(id).unwrap().downcast::<i32>().unwrap(), 1);
+    }
+
+    #[test]
+    fn id_lookup_off() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), 1);
+        assert!(res.get_by_id(asset.id()).is_none());
+        assert!(res.ids.is_empty());
+        res.set_id_lookup(true);
+        assert!(res.get_by_id(asset.id()).unwrap().ptr_eq(&asset.raw));
+        res.set_id_lookup(false);
+        assert!(res.ids.is_empty());
+        check_invariants(&res);
+    }
+
+    #[test]
+    fn get_by_id_long_key() {
+        for policy in [KeyLengthPolicy::Reject, KeyLengthPolicy::Truncate] {
+            let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+            res.set_id_lookup(true);
+            let long = res.insert(String::from("test-long"), 1);
+            let _short = res.insert(String::from("test-"), 2);
+            res.set_max_key_len(Some(5), policy);
+            assert!(res.get_by_id(long.id()).unwrap().ptr_eq(&long.raw));
+            check_invariants(&res);
+        }
+    }
+
+    #[test]
+    fn get_by_id_overwritten() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test"), 2);
+        assert!(res.get_by_id(asset1.id()).is_none());
+        assert!(res.get_by_id(asset2.id()).is_some());
+        assert_eq!(res.ids.len(), 1);
+    }
+
+    #[test]
+    fn get_by_id_evicted() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(1).unwrap());
+        res.set_id_lookup(true);
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test2"), 2);
+        let id1 = asset1.id();
+        res.remove(asset1.raw);
+        res.remove(asset2.raw);
+        assert!(res.get_by_id(id1).is_none());
+        assert_eq!(res.ids.len(), 1);
+    }
+
+    #[test]
+    fn capacity_remaining() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test2"), 2);
+        assert_eq!(res.capacity_remaining(), 2);
+        assert!(!res.would_evict());
+        res.remove(asset1.raw);
+        assert_eq!(res.capacity_remaining(), 1);
+        assert!(!res.would_evict());
+        res.remove(asset2.raw);
+        assert_eq!(res.capacity_remaining(), 0);
+        assert!(res.would_evict());
+        let _ = res.insert(String::from("test3"), 3);
+        assert_eq!(res.loaded.len(), 2);
+    }
+
+    #[test]
+    fn capacity_boundary_below() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(3).unwrap());
+        res.set_id_lookup(true);
+        let assets: Vec<_> = (0..3)
+            .map(|i| res.insert(format!("test{}", i), i))
+            .collect();
+        let mut assets = assets.into_iter();
+        res.remove(assets.next().unwrap().raw);
+        res.remove(assets.next().unwrap().raw);
+        // len == capacity - 1
+        assert_eq!(res.loaded.len(), 2);
+        assert!(!res.loaded_at_capacity());
+        res.remove(assets.next().unwrap().raw);
+        assert_eq!(res.loaded.len(), 3);
+        assert_eq!(res.ids.len(), 3);
+        assert!(res.loaded_at_capacity());
+    }
+
+    #[test]
+    fn capacity_boundary_full() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let assets: Vec<_> = (0..3)
+            .map(|i| res.insert(format!("test{}", i), i))
+            .collect();
+        let mut assets = assets.into_iter();
+        res.remove(assets.next().unwrap().raw);
+        res.remove(assets.next().unwrap().raw);
+        // len == capacity, the next demotion evicts the least recently used
+        assert!(res.loaded_at_capacity());
+        res.remove(assets.next().unwrap().raw);
+        assert_eq!(res.loaded.len(), 2);
+        assert!(res.loaded_at_capacity());
+        assert!(!res.loaded.contains("test0"));
+        // promoting frees a slot
+        let asset1 = res.get_raw("test1").unwrap();
+        assert!(!res.loaded_at_capacity());
+        res.remove(asset1);
+        assert!(res.loaded.contains("test2"));
+    }
+
+    #[test]
+    fn set_capacity() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(3).unwrap());
+        res.set_id_lookup(true);
+        for i in 0..3 {
+            let asset = res.insert(format!("test{}", i), i);
+            res.remove(asset.raw);
+        }
+        res.set_capacity(NonZeroUsize::new(1).unwrap());
+        assert_eq!(res.loaded.len(), 1);
+        assert_eq!(res.ids.len(), 1);
+        assert!(res.loaded.contains("test2"));
+    }
+
+    #[test]
+    fn temporary_capacity() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(1).unwrap());
+        {
+            let mut res = res.with_temporary_capacity(NonZeroUsize::new(3).unwrap());
+            for i in 0..3 {
+                let asset = res.insert(format!("test{}", i), i);
+                res.remove(asset.raw);
+            }
+            assert_eq!(res.loaded.len(), 3);
+        }
+        assert_eq!(res.loaded.cap().get(), 1);
+        assert_eq!(res.loaded.len(), 1);
+        assert!(res.loaded.contains("test2"));
+    }
+
+    #[test]
+    fn temporary_capacity_restores_watermarks() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(1).unwrap());
+        res.set_watermarks(1, NonZeroUsize::new(4).unwrap());
+        {
+            let mut res = res.with_temporary_capacity(NonZeroUsize::new(1).unwrap());
+            assert_eq!(res.watermarks, None);
+            res.set_watermarks(0, NonZeroUsize::new(2).unwrap());
+        }
+        assert_eq!(res.watermarks, Some((1, 4)));
+        assert_eq!(res.loaded.cap().get(), 4);
+    }
+
+    #[test]
+    fn watermarks_evict_in_bursts() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        res.set_watermarks(2, NonZeroUsize::new(4).unwrap());
+        let mut lens = Vec::new();
+        for i in 0..8 {
+            let asset = res.insert(format!("test{}", i), i);
+            res.remove(asset.raw);
+            lens.push(res.loaded.len());
+        }
+        assert_eq!(lens, [1, 2, 3, 4, 3, 4, 3, 4]);
+        assert_eq!(res.ids.len(), 4);
+        assert!(res.get_raw("test3").is_none());
+        assert_eq!(*res.get::<i32>("test4").unwrap(), 4);
+    }
+
+    #[test]
+    fn watermarks_shrink_existing() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(4).unwrap());
+        res.set_id_lookup(true);
+        for i in 0..4 {
+            let asset = res.insert(format!("test{}", i), i);
+            res.remove(asset.raw);
+        }
+        res.set_watermarks(0, NonZeroUsize::new(2).unwrap());
+        assert_eq!(res.loaded.len(), 2);
+        assert_eq!(res.ids.len(), 2);
+        assert!(res.get_raw("test2").is_some());
+    }
+
+    #[test]
+    #[should_panic]
+    fn watermarks_invalid() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_watermarks(2, NonZeroUsize::new(2).unwrap());
+    }
+
+    #[test]
+    fn pin_skips_victim() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test2"), 2);
+        let asset3 = res.insert(String::from("test3"), 3);
+        assert!(res.pin_with_priority("test", 0));
+        assert!(!res.pin_with_priority("missing", 0));
+        res.remove(asset1.raw);
+        res.remove(asset2.raw);
+        res.remove(asset3.raw);
+        assert!(res.get_raw("test2").is_none());
+        assert_eq!(*res.get::<i32>("test").unwrap(), 1);
+        assert_eq!(*res.get::<i32>("test3").unwrap(), 3);
+    }
+
+    #[test]
+    fn pin_long_keys() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let _asset = res.insert(String::from("test"), 1);
+        res.set_max_key_len(Some(4), KeyLengthPolicy::Truncate);
+        assert!(res.pin_with_priority("testlong", 0));
+        assert_eq!(res.pins.keys().collect::<Vec<_>>(), ["test"]);
+        assert!(res.unpin("testlong"));
+        res.set_max_key_len(Some(3), KeyLengthPolicy::Reject);
+        assert!(!res.pin_with_priority("test", 0));
+        assert!(!res.unpin("test"));
+        assert!(res.pins.is_empty());
+    }
+
+    #[test]
+    fn pin_escalation_order() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(3).unwrap());
+        for (i, priority) in [5, 1, 3].into_iter().enumerate() {
+            let asset = res.insert(format!("test{}", i), i);
+            res.pin_with_priority(&format!("test{}", i), priority);
+            res.remove(asset.raw);
+        }
+        // everything pinned, lowest priority goes first
+        let asset = res.insert(String::from("test3"), 3);
+        res.remove(asset.raw);
+        assert!(!res.loaded.contains("test1"));
+        assert!(!res.pins.contains_key("test1"));
+        // unpinned entries go before any pinned one
+        let asset = res.insert(String::from("test4"), 4);
+        res.remove(asset.raw);
+        assert!(!res.loaded.contains("test3"));
+        let asset = res.insert(String::from("test5"), 5);
+        res.remove(asset.raw);
+        assert!(!res.loaded.contains("test4"));
+        res.pin_with_priority("test5", 4);
+        let asset = res.insert(String::from("test6"), 6);
+        res.remove(asset.raw);
+        assert!(!res.loaded.contains("test2"));
+        assert!(res.loaded.contains("test0"));
+        assert!(res.loaded.contains("test5"));
+        assert_eq!(res.loaded.len(), 3);
+    }
+
+    #[test]
+    fn unpin() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(1).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test2"), 2);
+        res.pin_with_priority("test", 0);
+        assert!(res.unpin("test"));
+        assert!(!res.unpin("test"));
+        res.remove(asset1.raw);
+        res.remove(asset2.raw);
+        assert!(res.get_raw("test").is_none());
+    }
+
+    #[test]
+    fn borrow_handles() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(1).unwrap());
+        let owner = res.insert(String::from("test"), String::from("value"));
+        let borrows: Vec<_> = (0..3).map(|_| owner.borrow()).collect();
+        let borrow = borrows[0].clone();
+        res.remove(owner.raw);
+        assert!(res.loaded.contains("test"));
+        assert_eq!(*borrow, "value");
+        // still alive after eviction
+        let asset = res.insert(String::from("test2"), String::new());
+        res.remove(asset.raw);
+        assert!(!res.loaded.contains("test"));
+        assert_eq!(borrows[2].key(), "test");
+        assert_eq!(*borrows[2], "value");
+    }
+
+    #[test]
+    fn borrows_dropped() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let owner = res.insert(String::from("test"), 1);
+        let other = owner.clone();
+        drop(owner.borrow().clone());
+        assert_eq!(owner.raw.0.borrows.load(Ordering::Relaxed), 0);
+        // a clone is not a borrow, so the entry stays in use
+        res.remove(owner.raw);
+        assert!(res.in_use.contains_key("test"));
+        res.remove(other.raw);
+        assert!(res.loaded.contains("test"));
+    }
+
+    #[test]
+    fn revalidate() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(4).unwrap());
+        res.set_id_lookup(true);
+        let in_use = res.insert(String::from("in_use"), 1);
+        let loaded = res.insert(String::from("loaded"), 2);
+        res.remove(loaded.raw);
+        let _fresh = res.insert(String::from("fresh"), 10);
+        let _other = res.insert(String::from("other"), String::from("3"));
+        let mut reloaded = res.revalidate(|_, value: &i32| *value < 10, |key| key.len() as i32);
+        reloaded.sort();
+        assert_eq!(reloaded, ["in_use", "loaded"]);
+        // old handles keep the old value
+        assert_eq!(*in_use, 1);
+        assert_eq!(*res.get::<i32>("in_use").unwrap(), 6);
+        assert_eq!(*res.get::<i32>("loaded").unwrap(), 6);
+        assert_eq!(*res.get::<i32>("fresh").unwrap(), 10);
+        assert!(res.get_by_id(in_use.id()).is_none());
+        check_invariants(&res);
+    }
+
+    #[test]
+    fn revalidate_more_than_capacity() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        let cold = res.insert(String::from("cold"), String::from("cold"));
+        res.remove(cold.raw);
+        let assets: Vec<_> = (0..3).map(|i| res.insert(format!("k{}", i), i)).collect();
+        let mut reloaded = res.revalidate(|_, _: &i32| true, |key| key.len() as i32);
+        reloaded.sort();
+        assert_eq!(reloaded, ["k0", "k1", "k2"]);
+        // replaced in place, nothing evicted
+        assert_eq!(res.in_use.len(), 3);
+        assert!(res.loaded.contains("cold"));
+        for asset in &assets {
+            assert!(!res.in_use[asset.key()].ptr_eq(&asset.raw));
+            assert!(res.get_by_id(asset.id()).is_none());
+            assert_eq!(*res.get::<i32>(asset.key()).unwrap(), 2);
+        }
+        assert_eq!(res.ids.len(), 4);
+        check_invariants(&res);
+    }
+
+    #[test]
+    fn touch_many() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(3).unwrap());
+        let in_use = res.insert(String::from("in_use"), 0);
+        let assets: Vec<_> = (0..3)
+            .map(|i| res.insert(format!("test{}", i), i))
+            .collect();
+        for asset in assets {
+            res.remove(asset.raw);
+        }
+        assert_eq!(res.touch_many(&["test0", "missing", "in_use", "test1"]), 3);
+        assert_eq!(
+            res.unload_candidates(),
+            ["test2", "test0", "test1", "in_use"]
+        );
+        let asset = res.insert(String::from("new"), 3);
+        res.remove(asset.raw);
+        assert!(!res.loaded.contains("test2"));
+        assert!(res.loaded.contains("test0"));
+        assert!(res.in_use.contains_key(in_use.key()));
+    }
+
+    #[test]
+    fn remove_baseline() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        res.set_remove_baseline(1);
+        let asset = res.insert(String::from("test"), 1);
+        let monitor = asset.raw.clone();
+        let user = asset.clone();
+        res.remove(asset.raw);
+        assert!(res.in_use.contains_key("test"));
+        res.remove(user.raw);
+        assert!(res.loaded.contains("test"));
+        drop(monitor);
+        check_invariants(&res);
+    }
+
+    #[test]
+    fn compact() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        let assets: Vec<_> = (0..1000)
+            .map(|i| res.insert(format!("test{}", i), i))
+            .collect();
+        let kept: Vec<_> = assets.into_iter().step_by(100).collect();
+        for i in 0..1000 {
+            if i % 100 != 0 {
+                res.unload(&format!("test{}", i));
+            }
+        }
+        let capacity = res.in_use.capacity();
+        res.compact();
+        assert!(res.in_use.capacity() < capacity);
+        assert_eq!(res.in_use.len(), kept.len());
+        for asset in &kept {
+            assert!(res.get_raw(asset.key()).unwrap().ptr_eq(&asset.raw));
+        }
+        check_invariants(&res);
+    }
+
+    #[test]
+    fn shrink_loaded_to() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(3).unwrap());
+        res.set_id_lookup(true);
+        let assets: Vec<_> = (0..3)
+            .map(|i| res.insert(format!("test{}", i), i))
+            .collect();
+        for asset in assets {
+            res.remove(asset.raw);
+        }
+        let evicted: Vec<_> = res
+            .shrink_loaded_to(1)
+            .iter()
+            .map(|x| x.key().to_owned())
+            .collect();
+        assert_eq!(evicted, ["test0", "test1"]);
+        assert_eq!(res.loaded.len(), 1);
+        assert!(res.shrink_loaded_to(1).is_empty());
+        assert_eq!(res.loaded.cap().get(), 3);
+        // refills up to the original capacity
+        for i in 3..5 {
+            let asset = res.insert(format!("test{}", i), i);
+            res.remove(asset.raw);
+        }
+        assert_eq!(res.loaded.len(), 3);
+        check_invariants(&res);
+    }
+
+    #[test]
+    fn min_lifetime_burst() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(3).unwrap());
+        res.set_min_lifetime(Duration::from_millis(50));
+        for key in ["old", "old2"] {
+            let asset = res.insert(String::from(key), 0);
+            res.remove(asset.raw);
+        }
+        std::thread::sleep(Duration::from_millis(60));
+        // the old entries go first, even though the burst is less recently used
+        let burst: Vec<_> = (0..3).map(|i| res.insert(format!("new{}", i), i)).collect();
+        for asset in burst {
+            res.remove(asset.raw);
+        }
+        assert!(res.get_raw("old").is_none());
+        assert!(res.get_raw("old2").is_none());
+        for i in 0..3 {
+            assert!(res.loaded.contains(&format!("new{}", i)));
+        }
+    }
+
+    #[test]
+    fn min_lifetime_all_young() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_min_lifetime(Duration::from_secs(3600));
+        let assets: Vec<_> = (0..3)
+            .map(|i| res.insert(format!("test{}", i), i))
+            .collect();
+        // demoted in reverse, so plain LRU would evict test2 for test0
+        for asset in assets.into_iter().rev() {
+            res.remove(asset.raw);
+        }
+        assert_eq!(res.loaded.len(), 2);
+        assert!(!res.loaded.contains("test1"));
+        assert!(res.loaded.contains("test2"));
+    }
+
+    #[test]
+    fn evict_callback_keep() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_evict_callback(|key, _| {
+            if key == "test" {
+                EvictDecision::Keep
+            } else {
+                EvictDecision::Evict
+            }
+        });
+        for i in ["test", "test2", "test3"] {
+            let asset = res.insert(String::from(i), 1);
+            res.remove(asset.raw);
+        }
+        assert!(res.loaded.contains("test"));
+        assert!(!res.loaded.contains("test2"));
+        assert!(res.loaded.contains("test3"));
+    }
+
+    #[test]
+    fn evict_callback_keep_all() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let asked = Arc::new(AtomicUsize::new(0));
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let a = asked.clone();
+        res.set_evict_callback(move |_, _| {
+            a.fetch_add(1, Ordering::SeqCst);
+            EvictDecision::Keep
+        });
+        for i in ["test", "test2", "test3"] {
+            let asset = res.insert(String::from(i), 1);
+            res.remove(asset.raw);
+        }
+        assert_eq!(asked.load(Ordering::SeqCst), 2);
+        assert_eq!(res.loaded.len(), 2);
+        assert!(!res.loaded.contains("test"));
+    }
+
+    #[test]
+    fn evict_callback_offers_once() {
+        let offered = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut res = ResourceCache::new(NonZeroUsize::new(3).unwrap());
+        res.set_min_lifetime(Duration::from_secs(3600));
+        let sink = offered.clone();
+        res.set_evict_callback(move |key, _| {
+            sink.lock().unwrap().push(key.to_owned());
+            EvictDecision::Keep
+        });
+        for i in 0..4 {
+            let asset = res.insert(format!("t{}", i), i);
+            res.remove(asset.raw);
+        }
+        let mut offered = offered.lock().unwrap().clone();
+        assert_eq!(offered.len(), 3);
+        offered.sort();
+        offered.dedup();
+        assert_eq!(offered, ["t0", "t1", "t2"]);
+        assert_eq!(res.loaded.len(), 3);
+    }
+
+    #[test]
+    fn evict_callback_panic() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut res = ResourceCache::new(NonZeroUsize::new(1).unwrap());
+        let counter = calls.clone();
+        res.set_evict_callback(move |_, _| {
+            if counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                panic!("callback failed");
+            }
+            EvictDecision::Evict
+        });
+        let asset = res.insert(String::from("test"), 1);
+        res.remove(asset.raw);
+        let asset = res.insert(String::from("test2"), 2);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            res.remove(asset.raw);
+        }));
+        assert!(result.is_err());
+        for i in 3..5 {
+            let asset = res.insert(format!("test{}", i), i);
+            res.remove(asset.raw);
+        }
+        // still called after the panic
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn get_cow_owned() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        let asset = res.insert(String::from("test"), String::from("value"));
+        res.remove(asset.raw);
+        assert!(matches!(res.get_cow::<String>("test"), Some(Cow::Owned(v)) if v == "value"));
+        assert!(res.get_raw("test").is_none());
+        assert!(res.ids.is_empty());
+    }
+
+    #[test]
+    fn get_cow_borrowed() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), String::from("value"));
+        assert!(matches!(res.get_cow::<String>("test"), Some(Cow::Borrowed(v)) if v == "value"));
+        assert!(res.get_raw("test").unwrap().ptr_eq(&asset.raw));
+    }
+
+    #[test]
+    fn get_cow_wrong_type() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), 1);
+        res.remove(asset.raw);
+        assert!(res.get_cow::<String>("test").is_none());
+        // left in the loaded tier, where it can still be evicted
+        assert!(res.loaded.contains("test"));
+        assert!(res.in_use.is_empty());
+        assert_eq!(*res.get::<i32>("test").unwrap(), 1);
+    }
+
+    #[test]
+    fn get_cow_pinned_on_another_thread() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), String::from("value"));
+        let raw = asset.raw.clone();
+        drop(asset);
+        std::thread::spawn(move || drop(raw.downcast::<String>().unwrap().into_pin()))
+            .join()
+            .unwrap();
+        assert!(matches!(
+            res.get_cow::<String>("test"),
+            Some(Cow::Borrowed(_))
+        ));
+        assert_eq!(res.iter_mut::<String>().count(), 0);
+    }
+
+    #[test]
+    fn get_cow_pinned_stays_loaded() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        let asset = res.insert(String::from("test"), String::from("value"));
+        let pinned = asset.clone().into_pin();
+        drop(pinned);
+        res.remove(asset.raw);
+        assert!(matches!(
+            res.get_cow::<String>("test"),
+            Some(Cow::Borrowed(v)) if v == "value"
+        ));
+        assert!(res.loaded.contains("test"));
+        assert!(res.in_use.is_empty());
+        check_invariants(&res);
+    }
+
+    #[test]
+    fn drop_hook_per_type() {
+        use std::sync::Mutex;
+
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+        let mut res = ResourceCache::new(NonZeroUsize::new(1).unwrap());
+        let d = dropped.clone();
+        res.register_drop_hook(move |v: &i32| d.lock().unwrap().push(format!("int {}", v)));
+        let d = dropped.clone();
+        res.register_drop_hook(move |v: &String| d.lock().unwrap().push(format!("str {}", v)));
+
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test2"), String::from("a"));
+        let _ = res.insert(String::from("test3"), 1.0);
+        res.remove(asset1.raw);
+        assert!(dropped.lock().unwrap().is_empty());
+        // evicts test
+        let asset4 = res.insert(String::from("test4"), 4);
+        res.remove(asset4.raw);
+        assert_eq!(*dropped.lock().unwrap(), ["int 1"]);
+        drop(res);
+        assert_eq!(*dropped.lock().unwrap(), ["int 1", "int 4"]);
+        // outlives the cache
+        drop(asset2);
+        assert_eq!(*dropped.lock().unwrap(), ["int 1", "int 4", "str a"]);
+    }
+
+    #[test]
+    fn drop_hook_not_called_on_take() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let mut res = ResourceCache::new(NonZeroUsize::new(1).unwrap());
+        let d = dropped.clone();
+        res.register_drop_hook(move |_: &String| {
+            d.fetch_add(1, Ordering::SeqCst);
+        });
+        let asset = res.insert(String::from("test"), String::from("a"));
+        res.remove(asset.raw);
+        assert!(matches!(res.get_cow::<String>("test"), Some(Cow::Owned(_))));
+        assert_eq!(dropped.load(Ordering::SeqCst), 0);
+    }
+
+    // loader error for the fallible loader APIs
+    #[derive(Debug, PartialEq)]
+    pub(crate) enum LoadError {
+        Failed,
+        Cache(CacheError),
+    }
+
+    impl From<CacheError> for LoadError {
+        fn from(err: CacheError) -> Self {
+            LoadError::Cache(err)
+        }
+    }
+
+    #[test]
+    fn loaders_reject_long_keys() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        res.set_max_key_len(Some(4), KeyLengthPolicy::Reject);
+        let too_long = CacheError::KeyTooLong { len: 7, max: 4 };
+        let loads = std::cell::Cell::new(0);
+        let load = || {
+            loads.set(loads.get() + 1);
+            1
+        };
+        assert_eq!(
+            res.try_get_or_insert_with("toolong", load).unwrap_err(),
+            too_long
+        );
+        assert_eq!(
+            res.get_or_try_insert_with::<i32, LoadError>("toolong", || Ok(load()))
+                .unwrap_err(),
+            LoadError::Cache(too_long.clone())
+        );
+        assert_eq!(
+            res.get_or_refresh_with::<i32, LoadError>(
+                "toolong",
+                Duration::ZERO,
+                RefreshFailure::Propagate,
+                || Ok(load()),
+            )
+            .unwrap_err(),
+            LoadError::Cache(too_long.clone())
+        );
+        assert_eq!(loads.get(), 0);
+        assert_eq!(
+            res.try_insert_if_changed(String::from("toolong"), 1)
+                .unwrap_err(),
+            too_long
+        );
+        let handles = res.get_many_or_insert_with(&["toolong", "ok"], |keys| {
+            assert_eq!(keys, ["ok"]);
+            vec![(String::from("ok"), 1), (String::from("toolong"), 2)]
+        });
+        assert_eq!(handles.len(), 1);
+        assert_eq!(res.ids.len(), 1);
+        assert!(res.try_get_or_insert_with("ok", load).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "longer than the maximum")]
+    fn get_or_insert_with_long_key() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_max_key_len(Some(4), KeyLengthPolicy::Reject);
+        let _ = res.get_or_insert_with("toolong", || -> i32 { panic!("loader called") });
+    }
+
+    #[test]
+    fn get_or_insert_with() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.get_or_insert_with("test", || 1);
+        let asset2 = res.get_or_insert_with("test", || 2);
+        assert_eq!(*asset2, 1);
+        assert!(asset1.raw.ptr_eq(&asset2.raw));
+    }
+
+    #[test]
+    fn get_or_refresh_with_fresh() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let ttl = Duration::from_secs(3600);
+        let asset1 =
+            res.get_or_refresh_with::<_, LoadError>("test", ttl, RefreshFailure::Propagate, || {
+                Ok(1)
+            });
+        let asset2 =
+            res.get_or_refresh_with::<_, LoadError>("test", ttl, RefreshFailure::Propagate, || {
+                Ok(2)
+            });
+        assert!(asset1.unwrap().raw.ptr_eq(&asset2.unwrap().raw));
+    }
+
+    #[test]
+    fn get_or_refresh_with_expired() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res
+            .get_or_refresh_with::<_, LoadError>(
+                "test",
+                Duration::ZERO,
+                RefreshFailure::Propagate,
+                || Ok(2),
+            )
+            .unwrap();
+        assert_eq!(*asset2, 2);
+        assert!(!asset1.raw.ptr_eq(&asset2.raw));
+        assert!(res.get_raw("test").unwrap().ptr_eq(&asset2.raw));
+    }
+
+    #[test]
+    fn get_or_refresh_with_failure() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), 1);
+        let stale = res
+            .get_or_refresh_with::<i32, _>(
+                "test",
+                Duration::ZERO,
+                RefreshFailure::ServeStale,
+                || Err(LoadError::Failed),
+            )
+            .unwrap();
+        assert!(stale.raw.ptr_eq(&asset.raw));
+        let err = res.get_or_refresh_with::<i32, _>(
+            "test",
+            Duration::ZERO,
+            RefreshFailure::Propagate,
+            || Err(LoadError::Failed),
+        );
+        assert_eq!(err.unwrap_err(), LoadError::Failed);
+        assert!(res.get_raw("test").unwrap().ptr_eq(&asset.raw));
+        let missing = res.get_or_refresh_with::<i32, _>(
+            "missing",
+            Duration::ZERO,
+            RefreshFailure::ServeStale,
+            || Err(LoadError::Failed),
+        );
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn get_many_or_insert_with() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let _ = res.insert(String::from("test2"), 2);
+        let mut requested = Vec::new();
+        let handles = res.get_many_or_insert_with(&["test1", "test2", "test3"], |keys| {
+            requested.extend(keys.iter().map(|key| key.to_string()));
+            keys.iter()
+                .rev()
+                .map(|key| (key.to_string(), key[4..].parse().unwrap()))
+                .collect()
+        });
+        assert_eq!(requested, ["test1", "test3"]);
+        let values: Vec<i32> = handles.iter().map(|handle| **handle).collect();
+        assert_eq!(values, [1, 2, 3]);
+        assert_eq!(res.in_use.len(), 3);
+    }
+
+    #[test]
+    fn get_many_or_insert_with_partial() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let handles = res.get_many_or_insert_with(&["test1", "test2"], |_| {
+            vec![(String::from("test2"), 2), (String::from("extra"), 0)]
+        });
+        assert_eq!(handles.len(), 1);
+        assert_eq!(*handles[0], 2);
+        assert!(res.get_raw("test1").is_none());
+        assert!(res.get_raw("extra").is_some());
+    }
+
+    #[test]
+    fn get_many_or_insert_with_cached_and_duplicates() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        let cached = res.insert(String::from("test1"), 1);
+        let handles = res.get_many_or_insert_with(&["test1", "test2", "test2"], |_| {
+            vec![
+                (String::from("test1"), 10),
+                (String::from("test2"), 2),
+                (String::from("test2"), 20),
+            ]
+        });
+        assert!(handles[0].raw.ptr_eq(&cached.raw));
+        let values: Vec<i32> = handles.iter().map(|handle| **handle).collect();
+        assert_eq!(values, [1, 2, 2]);
+        assert!(res.get_raw("test1").unwrap().ptr_eq(&cached.raw));
+        assert!(res.get_raw("test2").unwrap().ptr_eq(&handles[1].raw));
+        let extra = res.insert(String::from("extra"), 3);
+        res.get_many_or_insert_with(&["test3"], |_| vec![(String::from("extra"), 30)]);
+        assert!(res.get_raw("extra").unwrap().ptr_eq(&extra.raw));
+        check_invariants(&res);
+    }
+
+    #[test]
+    fn get_many_or_insert_with_all_cached() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let _ = res.insert(String::from("test"), 1);
+        let handles = res.get_many_or_insert_with::<i32>(&["test"], |_| panic!("nothing to load"));
+        assert_eq!(*handles[0], 1);
+    }
+
+    #[test]
+    fn get_or_insert_with_ctx() {
+        struct Device {
+            loads: usize,
+        }
+        let mut device = Device { loads: 0 };
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        for _ in 0..2 {
+            let asset = res.get_or_insert_with_ctx("test", &mut device, |device| {
+                device.loads += 1;
+                1
+            });
+            assert_eq!(*asset, 1);
+        }
+        assert_eq!(device.loads, 1);
+    }
+
+    #[test]
+    fn get_or_try_insert_with() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        assert!(res
+            .get_or_try_insert_with::<i32, _>("test", || Err(LoadError::Failed))
+            .is_err());
+        assert!(res.get_raw("test").is_none());
+        let asset = res.get_or_try_insert_with::<_, LoadError>("test", || Ok(1));
+        assert_eq!(*asset.unwrap(), 1);
+    }
+
+    #[test]
+    fn get_or_insert_with_panic() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            res.get_or_insert_with::<i32>("test", || panic!("loader failed"))
+        }));
+        assert!(result.is_err());
+        assert!(res.in_use.is_empty());
+        assert!(res.loaded.is_empty());
+        assert!(res.ids.is_empty());
+        assert_eq!(*res.get_or_insert_with("test", || 1), 1);
+    }
+
+    #[test]
+    fn get_or_try_insert_with_panic() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), 1);
+        res.remove(asset.raw);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            res.get_or_try_insert_with::<String, LoadError>("test", || panic!("loader failed"))
+        }));
+        assert!(result.is_err());
+        // the existing entry was promoted by the lookup, but nothing was replaced
+        assert_eq!(res.in_use.len(), 1);
+        assert_eq!(*res.get::<i32>("test").unwrap(), 1);
+        assert!(res.get_raw("test2").is_none());
+    }
+
+    #[test]
+    fn from_iter() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test2"), 2);
+        let id2 = asset2.id();
+        res.remove(asset2.raw);
+        let raw2 = res.get_raw("test2").unwrap();
+        let entries = vec![
+            (String::from("test"), asset1.raw.clone()),
+            (String::from("test2"), raw2),
+        ];
+        drop(res);
+        let mut res2: ResourceCache = entries.into_iter().collect();
+        assert_eq!(res2.loaded.cap(), DEFAULT_CAPACITY);
+        assert_eq!(res2.in_use.len(), 1);
+        assert_eq!(res2.loaded.len(), 1);
+        assert!(res2.get_raw("test").unwrap().ptr_eq(&asset1.raw));
+        // indexes the entries collected so far
+        res2.set_id_lookup(true);
+        assert_eq!(*res2.get_by_id(id2).unwrap().downcast::<i32>().unwrap(), 2);
+    }
+
+    #[test]
+    fn extend_replaces() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        let asset1 = res.insert(String::from("test"), 1);
+        let mut res2 = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset2 = res2.insert(String::from("test"), 2);
+        res.extend([(String::from("test"), asset2.raw.clone())]);
+        assert!(res.get_by_id(asset1.id()).is_none());
+        assert_eq!(*res.get::<i32>("test").unwrap(), 2);
+        assert_eq!(res.ids.len(), 1);
+    }
+
+    #[test]
+    fn batch_eviction_order() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(3).unwrap());
+        let mut other = ResourceCache::new(NonZeroUsize::new(8).unwrap());
+        let batch: Vec<_> = (0..5)
+            .map(|i| {
+                let raw = other.insert(format!("test{}", i), i).raw;
+                other.unload(raw.key());
+                (String::new(), raw)
+            })
+            .collect();
+        // all equally cold, evicted in the order they were added
+        res.extend(batch);
+        assert_eq!(res.unload_candidates(), ["test2", "test3", "test4"]);
+        let asset = res.insert(String::from("new"), 5);
+        res.remove(asset.raw);
+        assert_eq!(res.unload_candidates(), ["test3", "test4", "new"]);
+    }
+
+    #[test]
+    fn batch_eviction_order_young() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(3).unwrap());
+        res.set_id_lookup(true);
+        res.set_min_lifetime(Duration::from_secs(3600));
+        let mut other = ResourceCache::new(NonZeroUsize::new(8).unwrap());
+        let mut batch: Vec<_> = (0..5)
+            .map(|i| {
+                let raw = other.insert(format!("test{}", i), i).raw;
+                other.unload(raw.key());
+                (String::new(), raw)
+            })
+            .collect();
+        batch.reverse();
+        // all too young, so each insert evicts the oldest loaded entry, not
+        // the least recently added one, whether or not their creation
+        // instants are equal: test2 for test1, then test1 for test0
+        res.extend(batch);
+        let mut kept = res.unload_candidates();
+        kept.sort_unstable();
+        assert_eq!(kept, ["test0", "test3", "test4"]);
+        check_invariants(&res);
+    }
+
+    #[test]
+    fn extend_skips_long_keys() {
+        let mut other = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let long = other.insert(String::from("toolong"), 1);
+        let short = other.insert(String::from("ok"), 2);
+        for policy in [KeyLengthPolicy::Reject, KeyLengthPolicy::Truncate] {
+            let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+            res.set_id_lookup(true);
+            res.set_max_key_len(Some(4), policy);
+            res.extend([
+                (String::new(), long.raw.clone()),
+                (String::new(), short.raw.clone()),
+            ]);
+            assert_eq!(res.ids.len(), 1);
+            assert!(!res.in_use.contains_key("toolong"));
+            assert!(res.get_raw("ok").is_some());
+        }
+    }
+
+    // xorshift, so the random tests below are reproducible without extra
+    // dependencies
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    fn check_invariants(res: &ResourceCache) {
+        for key in res.in_use.keys() {
+            assert!(!res.loaded.contains(key), "{} in both tiers", key);
+        }
+        if res.id_lookup {
+            assert_eq!(res.ids.len(), res.in_use.len() + res.loaded.len());
+        } else {
+            assert!(res.ids.is_empty());
+        }
+        for (id, key) in &res.ids {
+            let raw = res.in_use.get(&**key).or_else(|| res.loaded.peek(&**key));
+            assert_eq!(raw.map(RawHandle::id), Some(*id));
+        }
+        for (key, raw) in res.in_use.iter().chain(res.loaded.iter()) {
+            assert_eq!(raw.key(), key);
+        }
+    }
+
+    #[test]
+    fn random_remove_sequences() {
+        for seed in 1..200 {
+            let mut rng = Rng(seed);
+            let mut res = ResourceCache::new(NonZeroUsize::new(3).unwrap());
+            res.set_id_lookup(true);
+            let mut held: Vec<RawHandle> = Vec::new();
+            for _ in 0..200 {
+                let key = format!("test{}", rng.below(6));
+                match rng.below(5) {
+                    0 => held.push(res.insert(key, 0).into()),
+                    1 => held.extend(res.get_raw(&key)),
+                    2 if !held.is_empty() => {
+                        let i = rng.below(held.len());
+                        held.push(held[i].clone());
+                    }
+                    3 if !held.is_empty() => {
+                        held.swap_remove(rng.below(held.len()));
+                    }
+                    4 if !held.is_empty() => {
+                        let raw = held.swap_remove(rng.below(held.len()));
+                        let cached = res.in_use.get(raw.key()).is_some_and(|x| x.ptr_eq(&raw));
+                        let external = Arc::strong_count(&raw.0) - 1 - cached as usize;
+                        let in_use_before = res.in_use.len();
+                        let (key, id) = (raw.key().to_owned(), raw.id());
+                        res.remove(raw);
+                        if cached && external == 0 {
+                            assert_eq!(res.in_use.len(), in_use_before - 1);
+                            assert_eq!(res.loaded.peek(&key).map(RawHandle::id), Some(id));
+                        } else {
+                            assert_eq!(res.in_use.len(), in_use_before);
+                        }
+                    }
+                    _ => {}
+                }
+                check_invariants(&res);
+            }
+        }
+    }
 }
@@ -0,0 +1,154 @@
+use std::{collections::HashMap, num::NonZeroUsize, sync::Arc};
+
+use lru::LruCache;
+
+use crate::{Handle, RawHandle};
+
+/// A two tier cache storing any number of values under each key, e.g. the
+/// levels of detail of a mesh.
+///
+/// Values under the same key are separate entries: each one is demoted with
+/// [`remove`](Self::remove) and evicted on its own, so the loaded tier can
+/// hold only some of the values of a key.
+pub struct MultiResourceCache {
+    in_use: HashMap<String, Vec<RawHandle>>,
+    // by id, since several entries share a key
+    loaded: LruCache<u64, RawHandle>,
+    // key -> ids of its entries in `loaded`
+    loaded_ids: HashMap<String, Vec<u64>>,
+}
+
+impl MultiResourceCache {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            in_use: HashMap::new(),
+            loaded: LruCache::new(capacity),
+            loaded_ids: HashMap::new(),
+        }
+    }
+
+    /// Adds `value` after the values already stored under `key`.
+    pub fn insert_multi<T: Send + Sync + 'static>(&mut self, key: String, value: T) -> Handle<T> {
+        let handle = Handle::new(&key, None, value);
+        self.in_use
+            .entry(key)
+            .or_default()
+            .push(handle.clone().into());
+        handle
+    }
+
+    /// Returns every value of type `T` stored under `key`, values in use
+    /// first, each group in insertion order.
+    pub fn get_multi<T: Send + Sync + 'static>(&mut self, key: &str) -> Vec<Handle<T>> {
+        self.get_multi_raw(key)
+            .into_iter()
+            .filter_map(|raw| raw.downcast().ok())
+            .collect()
+    }
+
+    pub fn get_multi_raw(&mut self, key: &str) -> Vec<RawHandle> {
+        if let Some(ids) = self.loaded_ids.remove(key) {
+            let values = ids.iter().filter_map(|id| self.loaded.pop(id));
+            self.in_use
+                .entry(key.to_owned())
+                .or_default()
+                .extend(values);
+        }
+        self.in_use.get(key).cloned().unwrap_or_default()
+    }
+
+    /// Demotes the entry to the loaded tier if `value` is its last handle
+    /// outside the cache, evicting the least recently demoted entry if the
+    /// loaded tier is full.
+    pub fn remove(&mut self, value: RawHandle) {
+        if Arc::strong_count(&value.0) != 2 {
+            return;
+        }
+        let key = value.key();
+        let values = match self.in_use.get_mut(key) {
+            Some(values) => values,
+            None => return,
+        };
+        let index = match values.iter().position(|raw| raw.ptr_eq(&value)) {
+            Some(index) => index,
+            None => return,
+        };
+        values.remove(index);
+        if values.is_empty() {
+            self.in_use.remove(key);
+        }
+        self.loaded_ids
+            .entry(key.to_owned())
+            .or_default()
+            .push(value.id());
+        if let Some((_, evicted)) = self.loaded.push(value.id(), value) {
+            self.forget_loaded(&evicted);
+        }
+    }
+
+    /// Number of entries, counting each value under a key.
+    pub fn len(&self) -> usize {
+        self.in_use.values().map(Vec::len).sum::<usize>() + self.loaded.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.in_use.is_empty() && self.loaded.is_empty()
+    }
+
+    fn forget_loaded(&mut self, evicted: &RawHandle) {
+        let key = evicted.key();
+        if let Some(ids) = self.loaded_ids.get_mut(key) {
+            ids.retain(|&id| id != evicted.id());
+            if ids.is_empty() {
+                self.loaded_ids.remove(key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_per_key() {
+        let mut res = MultiResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let _lod0 = res.insert_multi(String::from("mesh"), 0);
+        let _lod1 = res.insert_multi(String::from("mesh"), 1);
+        let _name = res.insert_multi(String::from("mesh"), String::from("name"));
+        let lods: Vec<i32> = res.get_multi("mesh").iter().map(|x| **x).collect();
+        assert_eq!(lods, [0, 1]);
+        assert_eq!(res.get_multi::<String>("mesh").len(), 1);
+        assert!(res.get_multi::<i32>("missing").is_empty());
+        assert_eq!(res.len(), 3);
+    }
+
+    #[test]
+    fn evicts_per_entry() {
+        let mut res = MultiResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let lods: Vec<_> = (0..3)
+            .map(|i| res.insert_multi(String::from("mesh"), i))
+            .collect();
+        for lod in lods {
+            res.remove(lod.raw);
+        }
+        // the first demoted lod is evicted, the other two stay loaded
+        assert_eq!(res.len(), 2);
+        let lods: Vec<i32> = res.get_multi("mesh").iter().map(|x| **x).collect();
+        assert_eq!(lods, [1, 2]);
+        assert_eq!(res.loaded.len(), 0);
+        assert!(res.loaded_ids.is_empty());
+    }
+
+    #[test]
+    fn remove_keeps_referenced() {
+        let mut res = MultiResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let lod0 = res.insert_multi(String::from("mesh"), 0);
+        let lod1 = res.insert_multi(String::from("mesh"), 1);
+        let _other = lod0.clone();
+        res.remove(lod0.raw);
+        res.remove(lod1.raw);
+        assert_eq!(res.in_use["mesh"].len(), 1);
+        assert_eq!(res.loaded.len(), 1);
+    }
+}
@@ -0,0 +1,123 @@
+use std::{fmt, sync::OnceLock};
+
+use crate::{Handle, ResourceCache};
+
+/// An asset that is still loading, stored in the cache as a
+/// [`PendingHandle`] so the handle can be bound to before the value exists.
+///
+/// The value is set once, from any thread, and can't change afterwards, so
+/// references returned by [`poll`](Self::poll) stay valid. To set it from a
+/// loader thread, send it the [`RawHandle`](crate::RawHandle) and downcast
+/// it there, since [`Handle`]s are not `Send`.
+pub struct Pending<T> {
+    value: OnceLock<T>,
+}
+
+/// Handle to an asset that may still be loading, see
+/// [`ResourceCache::get_or_insert_pending`].
+pub type PendingHandle<T> = Handle<Pending<T>>;
+
+impl<T> Pending<T> {
+    pub const fn new() -> Self {
+        Self {
+            value: OnceLock::new(),
+        }
+    }
+
+    /// Fills in the loaded value. If it was already set, `value` is given
+    /// back and the first value is kept.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        self.value.set(value)
+    }
+
+    /// The value, or `None` while it is still loading.
+    pub fn poll(&self) -> Option<&T> {
+        self.value.get()
+    }
+
+    /// The value, or `placeholder` while it is still loading.
+    pub fn get_or<'a>(&'a self, placeholder: &'a T) -> &'a T {
+        self.poll().unwrap_or(placeholder)
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.poll().is_some()
+    }
+}
+
+impl<T> Default for Pending<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Pending<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.poll() {
+            Some(value) => f.debug_tuple("Pending").field(value).finish(),
+            None => f.write_str("Pending(<loading>)"),
+        }
+    }
+}
+
+impl ResourceCache {
+    /// Returns the pending asset under `key`, inserting an empty one if there
+    /// is none. The flag is `true` if it was inserted by this call, i.e. the
+    /// caller should start loading it and [`set`](Pending::set) the value;
+    /// other callers get the same handle and wait for it.
+    ///
+    /// The asset is stored as `Pending<T>`, so it is looked up as that type
+    /// and not as `T`, also after it has loaded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is rejected, like [`insert`](Self::insert).
+    pub fn get_or_insert_pending<T: Send + Sync + 'static>(
+        &mut self,
+        key: &str,
+    ) -> (PendingHandle<T>, bool) {
+        match self.get(key) {
+            Some(handle) => (handle, false),
+            None => (self.insert(key.to_owned(), Pending::new()), true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, thread};
+
+    use super::*;
+
+    #[test]
+    fn single_flight() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let (handle, created) = res.get_or_insert_pending::<i32>("test");
+        assert!(created);
+        let (handle2, created2) = res.get_or_insert_pending::<i32>("test");
+        assert!(!created2);
+        assert_eq!(handle.id(), handle2.id());
+        assert_eq!(handle2.poll(), None);
+        assert_eq!(*handle2.get_or(&-1), -1);
+        assert_eq!(handle.set(1), Ok(()));
+        assert_eq!(handle.set(2), Err(2));
+        assert_eq!(handle2.poll(), Some(&1));
+        assert_eq!(res.get::<Pending<i32>>("test").unwrap().poll(), Some(&1));
+    }
+
+    #[test]
+    fn set_from_another_thread() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let (handle, _) = res.get_or_insert_pending::<String>("test");
+        let loader = handle.raw.clone();
+        thread::spawn(move || {
+            let loader = loader.downcast::<Pending<String>>().unwrap();
+            loader.set(String::from("loaded")).unwrap();
+        })
+        .join()
+        .unwrap();
+        assert!(handle.is_ready());
+        assert_eq!(handle.get_or(&String::new()), "loaded");
+        assert_eq!(format!("{:?}", *handle), "Pending(\"loaded\")");
+    }
+}
@@ -0,0 +1,204 @@
+use crate::{CacheError, RawHandle, ResourceCache};
+
+/// An entry of a [`ResourceCache`] looked up without knowing its type, see
+/// [`ResourceCache::raw_entry`].
+pub enum RawEntry<'a> {
+    Occupied(OccupiedRawEntry<'a>),
+    Vacant(VacantRawEntry<'a>),
+}
+
+/// An entry in the in use tier.
+pub struct OccupiedRawEntry<'a> {
+    cache: &'a mut ResourceCache,
+    key: String,
+}
+
+pub struct VacantRawEntry<'a> {
+    cache: &'a mut ResourceCache,
+    key: String,
+}
+
+impl ResourceCache {
+    /// Looks up the entry under `key` for inspecting or replacing it without
+    /// knowing its type.
+    ///
+    /// Like [`get_raw`](Self::get_raw), an entry found in the loaded tier is
+    /// moved to the in use tier, so it must be given back with
+    /// [`remove`](Self::remove) to become evictable again.
+    ///
+    /// The key length limit applies as for [`try_insert`](Self::try_insert):
+    /// a rejected key is returned as [`CacheError::KeyTooLong`], and a
+    /// truncated key is the one the entry is looked up and stored under.
+    pub fn raw_entry(&mut self, key: &str) -> Result<RawEntry<'_>, CacheError> {
+        let key = self.check_key(key)?;
+        Ok(match self.get_raw(key) {
+            Some(raw) => RawEntry::Occupied(OccupiedRawEntry {
+                key: raw.key().to_owned(),
+                cache: self,
+            }),
+            None => RawEntry::Vacant(VacantRawEntry {
+                key: key.to_owned(),
+                cache: self,
+            }),
+        })
+    }
+}
+
+impl RawEntry<'_> {
+    pub fn key(&self) -> &str {
+        match self {
+            RawEntry::Occupied(entry) => entry.key(),
+            RawEntry::Vacant(entry) => entry.key(),
+        }
+    }
+}
+
+impl OccupiedRawEntry<'_> {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn get(&self) -> &RawHandle {
+        &self.cache.in_use[&self.key]
+    }
+
+    /// Stores `value` in place of the entry, returning the old handle.
+    ///
+    /// Fails with [`CacheError::KeyTooLong`] if the entry's key is over the
+    /// key length limit, i.e. it was stored before the limit was set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` was created under a different key.
+    pub fn replace(self, value: RawHandle) -> Result<RawHandle, CacheError> {
+        assert_eq!(value.key(), self.key, "handle inserted under another key");
+        let old = self.get().clone();
+        self.cache.insert_raw(value)?;
+        Ok(old)
+    }
+
+    /// Takes the entry out of the cache, like
+    /// [`ResourceCache::unload`].
+    pub fn remove(self) -> RawHandle {
+        match self.cache.unload(&self.key) {
+            Some((_, raw)) => raw,
+            None => unreachable!("entry is in the in use tier"),
+        }
+    }
+}
+
+impl VacantRawEntry<'_> {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Stores `value` under the entry's key. If `value` is its only handle
+    /// it goes straight to the loaded tier, since nothing would demote it.
+    ///
+    /// Fails with [`CacheError::KeyTooLong`] if `value`'s key is over the key
+    /// length limit, e.g. the untruncated key of an entry looked up with
+    /// [`KeyLengthPolicy::Truncate`](crate::KeyLengthPolicy::Truncate).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` was created under a different key.
+    pub fn insert(self, value: RawHandle) -> Result<(), CacheError> {
+        self.cache.check_handle_key(&value)?;
+        assert_eq!(value.key(), self.key, "handle inserted under another key");
+        self.cache.insert_raw(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::{Handle, KeyLengthPolicy};
+
+    #[test]
+    fn occupied_promotes() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let asset = res.insert(String::from("test"), 1);
+        res.remove(asset.raw);
+        match res.raw_entry("test").unwrap() {
+            RawEntry::Occupied(entry) => assert_eq!(entry.get().key(), "test"),
+            RawEntry::Vacant(_) => panic!("entry is loaded"),
+        }
+        assert!(res.in_use.contains_key("test"));
+        assert!(res.loaded.is_empty());
+    }
+
+    #[test]
+    fn replace() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        let asset = res.insert(String::from("test"), 1);
+        let new: RawHandle = Handle::new("test", None, String::from("new")).into();
+        let old = match res.raw_entry("test").unwrap() {
+            RawEntry::Occupied(entry) => entry.replace(new.clone()).unwrap(),
+            RawEntry::Vacant(_) => panic!("entry is in use"),
+        };
+        assert!(old.ptr_eq(&asset.raw));
+        assert_eq!(*res.get::<String>("test").unwrap(), "new");
+        assert_eq!(res.get_by_id(new.id()).unwrap().id(), new.id());
+        assert!(res.get_by_id(asset.id()).is_none());
+    }
+
+    #[test]
+    fn remove() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let _asset = res.insert(String::from("test"), 1);
+        match res.raw_entry("test").unwrap() {
+            RawEntry::Occupied(entry) => assert_eq!(entry.remove().key(), "test"),
+            RawEntry::Vacant(_) => panic!("entry is in use"),
+        }
+        assert!(res.get_raw("test").is_none());
+    }
+
+    #[test]
+    fn vacant_insert() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        match res.raw_entry("test").unwrap() {
+            RawEntry::Vacant(entry) => entry.insert(Handle::new("test", None, 1).into()).unwrap(),
+            RawEntry::Occupied(_) => panic!("cache is empty"),
+        }
+        // only referenced by the cache, so it was demoted
+        assert!(res.loaded.contains("test"));
+        assert_eq!(*res.get::<i32>("test").unwrap(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "another key")]
+    fn vacant_insert_other_key() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        if let Ok(RawEntry::Vacant(entry)) = res.raw_entry("test") {
+            let _ = entry.insert(Handle::new("other", None, 1).into());
+        }
+    }
+
+    #[test]
+    fn long_keys() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.set_id_lookup(true);
+        res.set_max_key_len(Some(4), KeyLengthPolicy::Reject);
+        assert!(matches!(
+            res.raw_entry("toolongkey"),
+            Err(CacheError::KeyTooLong { len: 10, max: 4 })
+        ));
+        res.set_max_key_len(Some(4), KeyLengthPolicy::Truncate);
+        let entry = res.raw_entry("toolongkey").unwrap();
+        assert_eq!(entry.key(), "tool");
+        let vacant = match entry {
+            RawEntry::Vacant(entry) => entry,
+            RawEntry::Occupied(_) => panic!("cache is empty"),
+        };
+        let long: RawHandle = Handle::new("toolongkey", None, 1).into();
+        assert!(vacant.insert(long).is_err());
+        assert!(res.ids.is_empty());
+        if let RawEntry::Vacant(entry) = res.raw_entry("toolongkey").unwrap() {
+            entry.insert(Handle::new("tool", None, 1).into()).unwrap();
+        }
+        assert_eq!(*res.get::<i32>("toolongkey").unwrap(), 1);
+    }
+}
@@ -0,0 +1,102 @@
+use std::{convert::Infallible, num::NonZeroUsize};
+
+use crate::{Handle, ResourceCache};
+
+type Loader<T, E> = Box<dyn FnMut(&str) -> Result<T, E> + Send>;
+
+/// A [`ResourceCache`] of a single asset type that loads assets on a miss
+/// with the loader it was created with, so it never needs an insert.
+///
+/// The loader is called whenever the asset is not cached: the first time a
+/// key is looked up, and again if it was evicted. Handles must be given back
+/// with [`remove`](Self::remove) once unused to become evictable, as with
+/// [`ResourceCache::remove`]; assets never given back stay cached.
+pub struct ReadThroughCache<T, E = Infallible> {
+    cache: ResourceCache,
+    loader: Loader<T, E>,
+}
+
+impl<T: Send + Sync + 'static> ReadThroughCache<T> {
+    pub fn new(capacity: NonZeroUsize, mut loader: impl FnMut(&str) -> T + Send + 'static) -> Self {
+        Self::with_fallible_loader(capacity, move |key| Ok(loader(key)))
+    }
+
+    pub fn get(&mut self, key: &str) -> Handle<T> {
+        match self.try_get(key) {
+            Ok(handle) => handle,
+            Err(never) => match never {},
+        }
+    }
+}
+
+impl<T: Send + Sync + 'static, E> ReadThroughCache<T, E> {
+    /// Creates a cache whose loader can fail, see [`try_get`](Self::try_get).
+    pub fn with_fallible_loader(
+        capacity: NonZeroUsize,
+        loader: impl FnMut(&str) -> Result<T, E> + Send + 'static,
+    ) -> Self {
+        Self {
+            cache: ResourceCache::new(capacity),
+            loader: Box::new(loader),
+        }
+    }
+
+    /// Returns the cached asset, or loads it. Errors are returned as is and
+    /// not cached, so the next lookup of the key calls the loader again.
+    pub fn try_get(&mut self, key: &str) -> Result<Handle<T>, E> {
+        if let Some(handle) = self.cache.get(key) {
+            return Ok(handle);
+        }
+        let value = (self.loader)(key)?;
+        // the inner cache has no key length limit, so this never fails
+        match self.cache.try_insert(key.to_owned(), value) {
+            Ok(handle) => Ok(handle),
+            Err(err) => unreachable!("{}", err),
+        }
+    }
+
+    pub fn remove(&mut self, handle: Handle<T>) {
+        self.cache.remove(handle.raw);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[test]
+    fn loads_on_miss() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let counter = loads.clone();
+        let mut res = ReadThroughCache::new(NonZeroUsize::new(1).unwrap(), move |key| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            key.len()
+        });
+        let asset = res.get("test");
+        assert_eq!(*asset, 4);
+        assert_eq!(*res.get("test"), 4);
+        assert_eq!(loads.load(Ordering::Relaxed), 1);
+        res.remove(asset);
+        let asset2 = res.get("test2");
+        res.remove(asset2);
+        // evicted by test2, so loaded again
+        assert_eq!(*res.get("test"), 4);
+        assert_eq!(loads.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn fallible_loader() {
+        let mut res =
+            ReadThroughCache::with_fallible_loader(NonZeroUsize::new(2).unwrap(), |key| {
+                key.parse::<i32>()
+            });
+        assert_eq!(*res.try_get("1").unwrap(), 1);
+        assert!(res.try_get("x").is_err());
+        assert!(res.cache.get_raw("x").is_none());
+    }
+}
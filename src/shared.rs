@@ -0,0 +1,245 @@
+use std::{
+    num::NonZeroUsize,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+use crate::{CacheError, Handle, RawHandle, ResourceCache};
+
+/// A [`ResourceCache`] behind a mutex, cheap to clone and share between
+/// threads.
+///
+/// A panic while the lock is held (e.g. inside a loader passed to
+/// [`get_or_insert_with`](Self::get_or_insert_with)) does not make the cache
+/// unusable: the poison is cleared on the next access. This is sound because
+/// user code runs while the cache is consistent: loaders run before the
+/// cache is mutated, an evict callback runs before the entry being demoted
+/// leaves the in use tier, and the idle callback runs once the entry that
+/// emptied it is fully moved or removed, so a panic never leaves an entry
+/// half inserted or half moved.
+#[derive(Clone)]
+pub struct SharedResourceCache {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    cache: Mutex<ResourceCache>,
+    // updated whenever a lock is released
+    in_use_len: AtomicUsize,
+    loaded_len: AtomicUsize,
+}
+
+impl SharedResourceCache {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                cache: Mutex::new(ResourceCache::new(capacity)),
+                in_use_len: AtomicUsize::new(0),
+                loaded_len: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Locks the cache, recovering it if a previous holder panicked.
+    pub fn lock(&self) -> SharedGuard<'_> {
+        let guard = self.inner.cache.lock().unwrap_or_else(|poisoned| {
+            self.inner.cache.clear_poison();
+            poisoned.into_inner()
+        });
+        SharedGuard {
+            guard,
+            inner: &self.inner,
+        }
+    }
+
+    /// Number of entries in the in use and loaded tiers, read without
+    /// locking.
+    ///
+    /// The counts are updated when a lock is released, so they may already be
+    /// out of date when returned and don't reflect changes made by a thread
+    /// currently holding the lock. Good enough to decide whether locking for
+    /// maintenance is worthwhile, but not for anything that needs exact
+    /// numbers.
+    pub fn approx_lens(&self) -> (usize, usize) {
+        (
+            self.inner.in_use_len.load(Ordering::Relaxed),
+            self.inner.loaded_len.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn insert<T: Send + Sync + 'static>(&self, key: String, value: T) -> Handle<T> {
+        self.lock().insert(key, value)
+    }
+
+    pub fn try_insert<T: Send + Sync + 'static>(
+        &self,
+        key: String,
+        value: T,
+    ) -> Result<Handle<T>, CacheError> {
+        self.lock().try_insert(key, value)
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self, key: &str) -> Option<Handle<T>> {
+        self.lock().get(key)
+    }
+
+    pub fn get_or_insert_with<T: Send + Sync + 'static>(
+        &self,
+        key: &str,
+        f: impl FnOnce() -> T,
+    ) -> Handle<T> {
+        self.lock().get_or_insert_with(key, f)
+    }
+
+    pub fn try_get_or_insert_with<T: Send + Sync + 'static>(
+        &self,
+        key: &str,
+        f: impl FnOnce() -> T,
+    ) -> Result<Handle<T>, CacheError> {
+        self.lock().try_get_or_insert_with(key, f)
+    }
+
+    pub fn get_or_try_insert_with<T: Send + Sync + 'static, E: From<CacheError>>(
+        &self,
+        key: &str,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> Result<Handle<T>, E> {
+        self.lock().get_or_try_insert_with(key, f)
+    }
+
+    pub fn get_raw(&self, key: &str) -> Option<RawHandle> {
+        self.lock().get_raw(key)
+    }
+
+    pub fn remove(&self, value: RawHandle) {
+        self.lock().remove(value)
+    }
+}
+
+/// Locked access to a [`SharedResourceCache`], returned by
+/// [`SharedResourceCache::lock`].
+pub struct SharedGuard<'a> {
+    guard: MutexGuard<'a, ResourceCache>,
+    inner: &'a Inner,
+}
+
+impl Deref for SharedGuard<'_> {
+    type Target = ResourceCache;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl DerefMut for SharedGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl Drop for SharedGuard<'_> {
+    fn drop(&mut self) {
+        self.inner
+            .in_use_len
+            .store(self.guard.in_use.len(), Ordering::Relaxed);
+        self.inner
+            .loaded_len
+            .store(self.guard.loaded.len(), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::tests::LoadError;
+
+    #[test]
+    fn shared_between_threads() {
+        let res = SharedResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let res2 = res.clone();
+        thread::spawn(move || {
+            let _ = res2.insert(String::from("test"), 1);
+        })
+        .join()
+        .unwrap();
+        assert_eq!(*res.get::<i32>("test").unwrap(), 1);
+    }
+
+    #[test]
+    fn recover_from_loader_panic() {
+        let res = SharedResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let _ = res.insert(String::from("test"), 1);
+        let res2 = res.clone();
+        let result = thread::spawn(move || {
+            let _ = res2.get_or_insert_with::<i32>("test2", || panic!("loader failed"));
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(res.inner.cache.is_poisoned());
+
+        assert!(res.get::<i32>("test2").is_none());
+        assert!(!res.inner.cache.is_poisoned());
+        assert_eq!(*res.get::<i32>("test").unwrap(), 1);
+        assert_eq!(*res.get_or_insert_with("test2", || 2), 2);
+    }
+
+    #[test]
+    fn approx_lens() {
+        let res = SharedResourceCache::new(NonZeroUsize::new(2).unwrap());
+        assert_eq!(res.approx_lens(), (0, 0));
+        let asset1 = res.insert(String::from("test"), 1);
+        let _ = res.insert(String::from("test2"), 2);
+        assert_eq!(res.approx_lens(), (2, 0));
+        {
+            let mut guard = res.lock();
+            guard.remove(asset1.into());
+            // not published until the lock is released
+            assert_eq!(res.approx_lens(), (2, 0));
+        }
+        assert_eq!(res.approx_lens(), (1, 1));
+    }
+
+    #[test]
+    fn loader_panic_leaves_no_entry() {
+        let res = SharedResourceCache::new(NonZeroUsize::new(2).unwrap());
+        res.lock().set_id_lookup(true);
+        let result = std::panic::catch_unwind(|| {
+            let _ =
+                res.get_or_try_insert_with::<i32, LoadError>("test", || panic!("loader failed"));
+        });
+        assert!(result.is_err());
+        assert!(res.get_raw("test").is_none());
+        assert!(res.lock().ids.is_empty());
+        assert_eq!(
+            *res.get_or_try_insert_with::<_, LoadError>("test", || Ok(1))
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn recover_from_evict_callback_panic() {
+        let res = SharedResourceCache::new(NonZeroUsize::new(1).unwrap());
+        res.lock().set_id_lookup(true);
+        res.lock()
+            .set_evict_callback(|_, _| panic!("callback failed"));
+        let a = res.insert(String::from("a"), 1);
+        res.remove(a.raw);
+        let b = res.insert(String::from("b"), 2);
+        let id = b.id();
+        let raw = b.raw;
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| res.remove(raw)));
+        assert!(result.is_err());
+        // neither entry was lost
+        let guard = res.lock();
+        assert!(guard.loaded.contains("a"));
+        assert!(guard.in_use.contains_key("b"));
+        assert_eq!(guard.ids.get(&id).map(|key| &key[..]), Some("b"));
+        assert_eq!(guard.ids.len(), 2);
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    num::NonZeroU64,
+    time::{Duration, Instant},
+};
+
+use crate::ResourceCache;
+
+/// Counters of a [`ResourceCache`], see [`ResourceCache::stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups that found an entry in either tier.
+    pub hits: u64,
+    /// Lookups that found no entry.
+    pub misses: u64,
+    pub inserts: u64,
+    /// Entries evicted from the loaded tier to make room.
+    pub evictions: u64,
+    /// Lookups and inserts that grew the in use map, causing a rehash. Only
+    /// counted with the `alloc-telemetry` feature, otherwise always 0.
+    pub rehashes: u64,
+    pub in_use: usize,
+    pub loaded: usize,
+}
+
+/// How often a reporter set with [`ResourceCache::set_stats_reporter`] is
+/// called.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsInterval {
+    /// After this many lookups and inserts.
+    Operations(NonZeroU64),
+    /// On the first lookup or insert after this much time has passed.
+    Elapsed(Duration),
+}
+
+pub(crate) struct StatsReporter {
+    interval: StatsInterval,
+    callback: Box<dyn FnMut(&CacheStats) + Send>,
+    ops: u64,
+    last: Instant,
+}
+
+impl ResourceCache {
+    /// Snapshot of the counters and current tier sizes.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            in_use: self.in_use.len(),
+            loaded: self.loaded.len(),
+            ..self.stats
+        }
+    }
+
+    /// Calls `reporter` with a [`stats`](Self::stats) snapshot every
+    /// `interval`, replacing any previous reporter.
+    ///
+    /// The cache has no thread of its own, so [`StatsInterval::Elapsed`] is
+    /// only checked when the cache is used.
+    pub fn set_stats_reporter(
+        &mut self,
+        interval: StatsInterval,
+        reporter: Box<dyn FnMut(&CacheStats) + Send>,
+    ) {
+        self.stats_reporter = Some(StatsReporter {
+            interval,
+            callback: reporter,
+            ops: 0,
+            last: Instant::now(),
+        });
+    }
+
+    pub fn clear_stats_reporter(&mut self) {
+        self.stats_reporter = None;
+    }
+
+    // Called after each lookup and insert.
+    pub(crate) fn tick_stats(&mut self) {
+        #[cfg(feature = "alloc-telemetry")]
+        if self.in_use.capacity() > self.in_use_capacity {
+            self.in_use_capacity = self.in_use.capacity();
+            self.stats.rehashes += 1;
+        }
+        let stats = self.stats();
+        let reporter = match &mut self.stats_reporter {
+            Some(reporter) => reporter,
+            None => return,
+        };
+        reporter.ops += 1;
+        let due = match reporter.interval {
+            StatsInterval::Operations(ops) => reporter.ops >= ops.get(),
+            StatsInterval::Elapsed(interval) => reporter.last.elapsed() >= interval,
+        };
+        if due {
+            reporter.ops = 0;
+            reporter.last = Instant::now();
+            (reporter.callback)(&stats);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        num::NonZeroUsize,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+
+    fn collect(res: &mut ResourceCache, interval: StatsInterval) -> Arc<Mutex<Vec<CacheStats>>> {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        res.set_stats_reporter(
+            interval,
+            Box::new(move |stats| sink.lock().unwrap().push(*stats)),
+        );
+        reports
+    }
+
+    #[test]
+    fn counters() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(1).unwrap());
+        let asset = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test2"), 2);
+        assert!(res.get::<i32>("test").is_some());
+        assert!(res.get::<i32>("missing").is_none());
+        res.remove(asset.raw);
+        res.remove(asset2.raw);
+        assert_eq!(
+            res.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                inserts: 2,
+                evictions: 1,
+                rehashes: res.stats().rehashes,
+                in_use: 0,
+                loaded: 1,
+            }
+        );
+    }
+
+    #[cfg(feature = "alloc-telemetry")]
+    #[test]
+    fn rehashes() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let assets: Vec<_> = (0..100)
+            .map(|i| res.insert(format!("test{}", i), i))
+            .collect();
+        let rehashes = res.stats().rehashes;
+        assert!(rehashes > 0);
+        // lookups of entries already in use never grow the map
+        for asset in &assets {
+            let _ = res.get::<i32>(asset.key());
+        }
+        assert_eq!(res.stats().rehashes, rehashes);
+    }
+
+    #[test]
+    fn report_every_n_operations() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let reports = collect(
+            &mut res,
+            StatsInterval::Operations(NonZeroU64::new(2).unwrap()),
+        );
+        let _asset = res.insert(String::from("test"), 1);
+        assert!(reports.lock().unwrap().is_empty());
+        let _ = res.get::<i32>("test");
+        let _ = res.get::<i32>("missing");
+        let _ = res.get::<i32>("missing");
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!((reports[0].inserts, reports[0].hits), (1, 1));
+        assert_eq!(reports[1].misses, 2);
+    }
+
+    #[test]
+    fn report_elapsed() {
+        let mut res = ResourceCache::new(NonZeroUsize::new(2).unwrap());
+        let reports = collect(&mut res, StatsInterval::Elapsed(Duration::from_millis(20)));
+        let _ = res.get::<i32>("missing");
+        assert!(reports.lock().unwrap().is_empty());
+        std::thread::sleep(Duration::from_millis(30));
+        let _ = res.get::<i32>("missing");
+        assert_eq!(reports.lock().unwrap().len(), 1);
+        res.clear_stats_reporter();
+        std::thread::sleep(Duration::from_millis(30));
+        let _ = res.get::<i32>("missing");
+        assert_eq!(reports.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,131 @@
+use std::{num::NonZeroUsize, sync::Arc};
+
+use lru::LruCache;
+
+use crate::{Handle, RawHandle};
+
+/// A single tier alternative to [`ResourceCache`](crate::ResourceCache).
+///
+/// All entries share one LRU order. When the cache grows past its capacity,
+/// the least recently used entries that are not referenced outside the cache
+/// are evicted; entries with a live handle are skipped. An entry therefore
+/// becomes evictable as soon as its last handle is dropped, without calling
+/// `remove`.
+///
+/// The price is that capacity is a soft limit: if every entry is referenced,
+/// nothing can be evicted and the cache stays over capacity until handles are
+/// dropped and the next insert evicts them. Finding a victim also walks past
+/// the referenced entries, which the two tier cache never has to look at.
+pub struct UnifiedCache {
+    entries: LruCache<String, RawHandle>,
+    capacity: NonZeroUsize,
+}
+
+impl UnifiedCache {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            entries: LruCache::unbounded(),
+            capacity,
+        }
+    }
+
+    pub fn insert<T: Send + Sync + 'static>(&mut self, key: String, value: T) -> Handle<T> {
+        let handle = Handle::new(&key, None, value);
+        self.entries.put(key, handle.clone().into());
+        self.evict();
+        handle
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&mut self, key: &str) -> Option<Handle<T>> {
+        self.get_raw(key).and_then(|x| x.downcast().ok())
+    }
+
+    pub fn get_raw(&mut self, key: &str) -> Option<RawHandle> {
+        self.entries.get(key).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Evicts unreferenced entries, least recently used first, until the
+    // cache fits its capacity.
+    fn evict(&mut self) {
+        let excess = self.entries.len().saturating_sub(self.capacity.get());
+        if excess == 0 {
+            return;
+        }
+        let victims: Vec<String> = self
+            .entries
+            .iter()
+            .rev()
+            .filter(|(_, raw)| Arc::strong_count(&raw.0) == 1)
+            .map(|(key, _)| key.clone())
+            .take(excess)
+            .collect();
+        for key in victims {
+            self.entries.pop(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResourceCache;
+
+    #[test]
+    fn evicts_unreferenced() {
+        let mut res = UnifiedCache::new(NonZeroUsize::new(2).unwrap());
+        let _ = res.insert(String::from("test"), 1);
+        let _ = res.insert(String::from("test2"), 2);
+        let _ = res.insert(String::from("test3"), 3);
+        assert_eq!(res.len(), 2);
+        assert!(res.get_raw("test").is_none());
+    }
+
+    #[test]
+    fn skips_referenced() {
+        let mut res = UnifiedCache::new(NonZeroUsize::new(2).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        let _ = res.insert(String::from("test2"), 2);
+        let _ = res.insert(String::from("test3"), 3);
+        assert!(res.get_raw("test2").is_none());
+        assert!(res.get_raw("test").unwrap().ptr_eq(&asset1.raw));
+    }
+
+    #[test]
+    fn over_capacity_while_referenced() {
+        let mut res = UnifiedCache::new(NonZeroUsize::new(1).unwrap());
+        let asset1 = res.insert(String::from("test"), 1);
+        let asset2 = res.insert(String::from("test2"), 2);
+        assert_eq!(res.len(), 2);
+        drop(asset1);
+        drop(asset2);
+        // evicted on the next insert, not when the handles are dropped
+        assert_eq!(res.len(), 2);
+        let _ = res.insert(String::from("test3"), 3);
+        assert_eq!(res.len(), 1);
+        assert_eq!(*res.get::<i32>("test3").unwrap(), 3);
+    }
+
+    #[test]
+    fn compared_to_two_tier() {
+        let mut two_tier = ResourceCache::new(NonZeroUsize::new(1).unwrap());
+        let mut unified = UnifiedCache::new(NonZeroUsize::new(1).unwrap());
+        for i in 0..3 {
+            // handles dropped without calling `remove`
+            let _ = two_tier.insert(format!("test{}", i), i);
+            let _ = unified.insert(format!("test{}", i), i);
+        }
+        // the two tier cache never demotes them, the unified one evicts them
+        assert_eq!(two_tier.in_use.len(), 3);
+        assert!(two_tier.loaded.is_empty());
+        assert_eq!(unified.len(), 1);
+        assert!(unified.get_raw("test2").is_some());
+    }
+}